@@ -1,50 +1,715 @@
-use chrono::{Datelike, NaiveDate, Weekday};
-use clap::{Parser, Subcommand};
+use chrono::{DateTime, Datelike, NaiveDate, Utc, Weekday};
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
 use csv::StringRecord;
 use directories::ProjectDirs;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 use std::{
+    cell::OnceCell,
     cmp::Ordering,
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
     fs,
     io::{self, Write},
     path::{Path, PathBuf},
+    rc::Rc,
 };
 use walkdir::WalkDir;
 
+mod tui;
+
 #[derive(Parser)]
 #[command(
     name = "report-builder",
     author,
     version,
-    about = "CLI utility for configuring report paths."
+    about = "CLI utility for configuring report paths.",
+    after_help = EXIT_CODES_HELP
 )]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Path to the vosslabhpc share; overrides REPORT_BUILDER_SHARE_PATH and the config file.
+    /// With `init`, the value to save without prompting.
+    #[arg(long, global = true, value_name = "PATH")]
+    share_path: Option<String>,
+    /// Read and write this config file instead of the per-user config.toml. System and
+    /// project config files are still read.
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Use this GGIR version instead of the newest `GGIR-*` folder found for a subject.
+    #[arg(long, global = true, value_name = "VERSION")]
+    ggir_version: Option<String>,
+    /// Use this dataset folder or pattern for a study instead of its configured one, as
+    /// `STUDY=NAME`. A bare NAME is accepted only when the subjects all belong to one study.
+    /// Without it, each study uses the newest `act-*-final-test-*` dataset that holds the subject.
+    #[arg(long, global = true, value_name = "[STUDY=]NAME")]
+    dataset: Option<String>,
+    /// How a subject's study folder is found: from its number prefix, or by scanning every study.
+    #[arg(long, global = true, value_enum, value_name = "MODE")]
+    lookup: Option<LookupMode>,
+    /// Pick GGIR part5 day summaries whose file name matches this pattern (`*` and `?` wildcards).
+    #[arg(long, global = true, value_name = "PATTERN")]
+    variant: Option<String>,
+    /// Summarize every matching part5 variant instead of asking for exactly one.
+    #[arg(long, global = true)]
+    all_variants: bool,
+    /// Report extra detail on stderr, such as which CSV header each required column was read from.
+    #[arg(long, short, global = true)]
+    verbose: bool,
+    /// Use the named profile from the config file; overrides REPORT_BUILDER_PROFILE.
+    /// With `init`, the profile to create or edit.
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+    /// How errors are written to stderr.
+    #[arg(long, global = true, value_enum, default_value_t = ErrorFormat::Text)]
+    error_format: ErrorFormat,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Initialize the path to the vosslabhpc share.
-    Init,
+    /// Initialize the path to the vosslabhpc share (uses --share-path when given).
+    Init {
+        /// Save the share path even if it does not look like the vosslabhpc share.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show how a subject would be resolved and read, without computing summaries.
+    Explain {
+        /// Subject number to explain.
+        subject: String,
+    },
+    /// Browse subjects and their summaries in a full-screen terminal UI.
+    Tui,
+    /// Inspect or edit the configuration file.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Print a shell completion script to stdout.
+    Completions {
+        /// Shell to generate completions for.
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print the man page (roff) to stdout.
+    Manpage,
+    /// Summarize one or more subjects without prompting.
+    Report {
+        /// Subject numbers to summarize (by default four digits starting with 7, 8, or 9).
+        #[arg(required = true)]
+        subjects: Vec<String>,
+        /// Output format for the summaries.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    /// Summarize a cohort of subjects from a list file or numeric range.
+    #[command(group(ArgGroup::new("subjects").required(true).args(["file", "range"])))]
+    Batch {
+        /// Text or CSV file with one subject number per line (first column is used).
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Inclusive range of subject numbers, e.g. 7001-7099.
+        #[arg(long)]
+        range: Option<String>,
+        /// Output format for the cohort table.
+        #[arg(long, value_enum, default_value_t = TableFormat::Table)]
+        format: TableFormat,
+        /// Write the cohort table to this file instead of stdout.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// List every subject folder under each study, dataset and GGIR version with its part5 data.
+    Discover {
+        /// Output format for the inventory.
+        #[arg(long, value_enum, default_value_t = TableFormat::Table)]
+        format: TableFormat,
+        /// Write the inventory to this file instead of stdout.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Copy subjects' part5 CSVs into a local cache that later runs can read offline.
+    Sync {
+        /// Subject numbers to cache.
+        subjects: Vec<String>,
+        /// Text or CSV file with one subject number per line (first column is used).
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Inclusive range of subject numbers, e.g. 7001-7099.
+        #[arg(long)]
+        range: Option<String>,
+        /// Check cached files against the share instead of copying; without subjects, checks all.
+        #[arg(long)]
+        verify: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print every value in effect after merging the system, user, project and environment layers.
+    Show {
+        /// Note after each value the layer it came from.
+        #[arg(long)]
+        origin: bool,
+    },
+    /// Print a single value in effect.
+    Get { key: String },
+    /// Set a single value and save the config file; separate `share_roots` entries with commas.
+    Set { key: String, value: String },
+    /// Check that every config file parses and the share is reachable.
+    Validate,
+    /// Print the location of the per-user config file, which `init` and `set` write.
+    Path,
+    /// Rewrite the config file in the current format; the old file, comments included, is kept
+    /// as `<file>.v<version>.bak`.
+    Migrate,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ErrorFormat {
+    Text,
+    Json,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum LookupMode {
+    #[default]
+    Prefix,
+    Scan,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TableFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 struct Config {
-    share_path: String,
+    /// Format version of the file; see `CONFIG_VERSION`. Files without one are version 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    share_path: Option<SharePathSetting>,
+    /// More share roots, such as a local mirror, tried in order after `share_path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    share_roots: Option<Vec<String>>,
+    /// Path template for a subject's accel folder; see `DEFAULT_LAYOUT`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    layout: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ggir_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    subject_digits: Option<usize>,
+    /// File name pattern selecting the GGIR part5 day summary; see `DEFAULT_PART5_PATTERN`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    part5_pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lookup: Option<LookupMode>,
+    /// Maps subject-number prefixes to study folders; replaces the built-in table when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    studies: Option<Vec<StudyRule>>,
+    /// Extra header names for required part5 columns, e.g. `dur_spt_min = ["SleepDuration"]`;
+    /// tried before the built-in aliases.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    column_aliases: Option<BTreeMap<String, Vec<String>>>,
+    /// Named sets of keys, e.g. `[profiles.pilot]`, laid over the top-level keys when selected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    profiles: Option<BTreeMap<String, Config>>,
+    // Keys this build does not know, kept so saving the file does not drop them.
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+
+/// The config format this build writes. Bump it, and add a step to `migrate_config`, whenever
+/// a change needs older files rewritten.
+const CONFIG_VERSION: u32 = 2;
+
+impl Config {
+    /// Fills every key this config leaves unset from `base`.
+    fn or(self, base: Config) -> Config {
+        let mut unknown = base.unknown;
+        unknown.extend(self.unknown);
+        Config {
+            version: self.version.or(base.version),
+            share_path: self.share_path.or(base.share_path),
+            share_roots: self.share_roots.or(base.share_roots),
+            layout: self.layout.or(base.layout),
+            ggir_version: self.ggir_version.or(base.ggir_version),
+            subject_digits: self.subject_digits.or(base.subject_digits),
+            part5_pattern: self.part5_pattern.or(base.part5_pattern),
+            lookup: self.lookup.or(base.lookup),
+            studies: self.studies.or(base.studies),
+            column_aliases: self.column_aliases.or(base.column_aliases),
+            // Profiles of the same name are merged key by key, like the top level.
+            profiles: match (self.profiles, base.profiles) {
+                (Some(mut profiles), Some(base_profiles)) => {
+                    for (name, base_profile) in base_profiles {
+                        let profile = match profiles.remove(&name) {
+                            Some(profile) => profile.or(base_profile),
+                            None => base_profile,
+                        };
+                        profiles.insert(name, profile);
+                    }
+                    Some(profiles)
+                }
+                (profiles, base_profiles) => profiles.or(base_profiles),
+            },
+            unknown,
+        }
+    }
+
+    /// The keys this config sets, not counting `profiles`.
+    fn set_keys(&self) -> Vec<String> {
+        toml::Table::try_from(self)
+            .map(|table| {
+                table
+                    .keys()
+                    .filter(|key| *key != "profiles")
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Keys this build ignores, with profile keys written as `profiles.NAME.key`.
+    fn unknown_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.unknown.keys().cloned().collect();
+        for (name, profile) in self.profiles.iter().flatten() {
+            keys.extend(
+                profile
+                    .unknown_keys()
+                    .into_iter()
+                    .map(|key| format!("profiles.{}.{}", name, key)),
+            );
+        }
+        keys
+    }
+
+    /// The top-level keys with the named profile laid over them.
+    fn with_profile(mut self, name: &str) -> Result<Config, String> {
+        let mut profiles = self.profiles.take().unwrap_or_default();
+        match profiles.remove(name) {
+            Some(profile) => Ok(profile.or(self)),
+            None if profiles.is_empty() => Err(format!(
+                "Profile '{}' is not defined; the config file has no profiles.",
+                name
+            )),
+            None => Err(format!(
+                "Profile '{}' is not defined. Known profiles: {}",
+                name,
+                profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+}
+
+/// `share_path` as one path, or as a table with one path per operating system so a single
+/// config file works on every workstation.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum SharePathSetting {
+    Single(String),
+    PerOs(BTreeMap<String, String>),
+}
+
+/// Keys allowed in a per-OS `share_path` table; `default` covers any other platform.
+const SHARE_PATH_PLATFORMS: [&str; 4] = ["macos", "windows", "linux", "default"];
+
+impl SharePathSetting {
+    /// The entry for the running operating system, falling back to `default`.
+    fn for_this_os(&self) -> Option<&str> {
+        let path = match self {
+            SharePathSetting::Single(path) => Some(path),
+            SharePathSetting::PerOs(paths) => paths
+                .get(std::env::consts::OS)
+                .or_else(|| paths.get("default")),
+        };
+        path.map(|path| path.trim()).filter(|path| !path.is_empty())
+    }
+}
+
+/// One row of the prefix table, e.g. subjects starting with 7 belong to ObservationalStudy.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct StudyRule {
+    prefixes: Vec<String>,
+    study: String,
+    /// A dataset folder name, or a pattern such as `act-obs-final-test-*` to use the newest match.
+    dataset: String,
+}
+
+const CONFIG_KEYS: [&str; 7] = [
+    "share_path",
+    "share_roots",
+    "layout",
+    "ggir_version",
+    "subject_digits",
+    "part5_pattern",
+    "lookup",
+];
+const DEFAULT_SUBJECT_DIGITS: usize = 4;
+
+/// Each study uses the newest `act-*-final-test-*` dataset that holds the subject, with a warning
+/// when a newer one lacks it. Earlier releases pinned the defaults to `act-*-final-test-2`; pin a
+/// folder with `studies` in the config or `--dataset` instead.
+fn default_studies() -> Vec<StudyRule> {
+    vec![
+        StudyRule {
+            prefixes: vec!["7".to_string()],
+            study: "ObservationalStudy".to_string(),
+            dataset: "act-obs-final-test-*".to_string(),
+        },
+        StudyRule {
+            prefixes: vec!["8".to_string(), "9".to_string()],
+            study: "InterventionStudy".to_string(),
+            dataset: "act-int-final-test-*".to_string(),
+        },
+    ]
+}
+
+/// Where GGIR output for a subject lives today, relative to the share.
+const DEFAULT_LAYOUT: &str = "{share}/Projects/BOOST/{study}/3-experiment/data/{dataset}/derivatives/GGIR-{ggir_version}/sub-{subject}/accel";
+/// Used when a layout needs a GGIR version but no `GGIR-*` folder could be found.
+const DEFAULT_GGIR_VERSION: &str = "3.2.6";
+/// The part5 day summary used before variants were selectable: MM windows with the default cutpoints.
+const DEFAULT_PART5_PATTERN: &str = "part5_daysummary_MM_L44.8M100.6V428.8_T5A5.csv";
+const LAYOUT_PLACEHOLDERS: [&str; 5] = ["share", "study", "dataset", "ggir_version", "subject"];
+
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0   success
+  1   unexpected error
+  2   invalid command-line usage
+  3   no configuration found
+  4   configuration could not be parsed or is incomplete
+  5   invalid subject number
+  6   subject directory does not exist
+  7   no target CSV found for the subject
+  8   target CSV is missing required column(s)
+  9   insufficient data to compute a summary
+  10  file could not be read or written
+  11  every subject in a batch failed
+  12  share path is not reachable or lacks the expected layout
+  13  several part5 variants match and none was chosen
+  14  the subject was found in several study datasets and none was chosen
+  15  cached files no longer match the share";
+
+/// Every failure the CLI can report; each variant maps to a documented exit code.
+#[derive(Debug)]
+enum AppError {
+    ConfigMissing(PathBuf),
+    ConfigInvalid {
+        path: PathBuf,
+        message: String,
+    },
+    InvalidSubject {
+        subject: String,
+        reason: String,
+    },
+    SubjectDirectoryMissing(PathBuf),
+    NoCsvFound {
+        subject: String,
+        directory: PathBuf,
+    },
+    MissingColumns {
+        file: PathBuf,
+        columns: Vec<String>,
+    },
+    InsufficientData {
+        subject: String,
+    },
+    Io(String),
+    BatchFailed {
+        failed: usize,
+    },
+    ShareUnreachable {
+        path: PathBuf,
+        reason: String,
+    },
+    AmbiguousVariant {
+        subject: String,
+        variants: Vec<String>,
+    },
+    AmbiguousSubject {
+        subject: String,
+        matches: Vec<SubjectMatch>,
+    },
+    CacheStale {
+        stale: usize,
+    },
+    Other(String),
+}
+
+type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            AppError::Other(_) => 1,
+            AppError::ConfigMissing(_) => 3,
+            AppError::ConfigInvalid { .. } => 4,
+            AppError::InvalidSubject { .. } => 5,
+            AppError::SubjectDirectoryMissing(_) => 6,
+            AppError::NoCsvFound { .. } => 7,
+            AppError::MissingColumns { .. } => 8,
+            AppError::InsufficientData { .. } => 9,
+            AppError::Io(_) => 10,
+            AppError::BatchFailed { .. } => 11,
+            AppError::ShareUnreachable { .. } => 12,
+            AppError::AmbiguousVariant { .. } => 13,
+            AppError::AmbiguousSubject { .. } => 14,
+            AppError::CacheStale { .. } => 15,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            AppError::Other(_) => "other",
+            AppError::ConfigMissing(_) => "config_missing",
+            AppError::ConfigInvalid { .. } => "config_invalid",
+            AppError::InvalidSubject { .. } => "invalid_subject",
+            AppError::SubjectDirectoryMissing(_) => "subject_directory_missing",
+            AppError::NoCsvFound { .. } => "no_csv_found",
+            AppError::MissingColumns { .. } => "missing_columns",
+            AppError::InsufficientData { .. } => "insufficient_data",
+            AppError::Io(_) => "io",
+            AppError::BatchFailed { .. } => "batch_failed",
+            AppError::ShareUnreachable { .. } => "share_unreachable",
+            AppError::AmbiguousVariant { .. } => "ambiguous_variant",
+            AppError::AmbiguousSubject { .. } => "ambiguous_subject",
+            AppError::CacheStale { .. } => "cache_stale",
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "error": {
+                "kind": self.kind(),
+                "code": self.exit_code(),
+                "message": self.to_string(),
+            }
+        })
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::ConfigMissing(path) => write!(
+                f,
+                "No configuration found at {}. Please run `report-builder init`, pass --share-path, or set {}.",
+                path.display(),
+                SHARE_PATH_ENV
+            ),
+            AppError::ConfigInvalid { path, message } => {
+                write!(f, "Invalid configuration in {}: {}", path.display(), message)
+            }
+            AppError::InvalidSubject { subject, reason } => {
+                write!(f, "Invalid subject number '{}': {}", subject, reason)
+            }
+            AppError::SubjectDirectoryMissing(path) => {
+                write!(f, "Subject directory does not exist: {}", path.display())
+            }
+            AppError::NoCsvFound { subject, directory } => write!(
+                f,
+                "No matching files found for subject {} under {}",
+                subject,
+                directory.display()
+            ),
+            AppError::MissingColumns { file, columns } => write!(
+                f,
+                "File {} is missing required column(s): {}",
+                file.display(),
+                columns.join(", ")
+            ),
+            AppError::InsufficientData { subject } => write!(
+                f,
+                "Insufficient overlapping data to compute a weekly summary for subject {}.",
+                subject
+            ),
+            AppError::Io(message) | AppError::Other(message) => f.write_str(message),
+            AppError::BatchFailed { failed } => write!(
+                f,
+                "No subjects could be summarized ({} failed).",
+                failed
+            ),
+            AppError::ShareUnreachable { path, reason } => {
+                write!(f, "The share path {} is not usable: {}", path.display(), reason)
+            }
+            AppError::AmbiguousVariant { subject, variants } => write!(
+                f,
+                "Subject {} has {} part5 variants: {}. Narrow the pattern with --variant or pass --all-variants.",
+                subject,
+                variants.len(),
+                variants.join(", ")
+            ),
+            AppError::AmbiguousSubject { subject, matches } => write!(
+                f,
+                "Subject {} was found in {} places: {}. Choose one with --dataset STUDY=DATASET.",
+                subject,
+                matches.len(),
+                matches
+                    .iter()
+                    .map(SubjectMatch::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            AppError::CacheStale { stale } => write!(
+                f,
+                "{} cached file(s) no longer match the share. Run `report-builder sync` again.",
+                stale
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<io::Error> for AppError {
+    fn from(err: io::Error) -> Self {
+        AppError::Io(err.to_string())
+    }
+}
+
+impl From<csv::Error> for AppError {
+    fn from(err: csv::Error) -> Self {
+        AppError::Io(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        AppError::Other(err.to_string())
+    }
+}
+
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::Other(message)
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        AppError::Other(message.to_string())
+    }
+}
+
+const SHARE_PATH_ENV: &str = "REPORT_BUILDER_SHARE_PATH";
+const PROFILE_ENV: &str = "REPORT_BUILDER_PROFILE";
+/// Config file read from the working directory, above the per-user file.
+const PROJECT_CONFIG_FILE: &str = "report-builder.toml";
+
+/// Command-line settings that take precedence over the config file.
+struct ConfigOverrides {
+    share_path: Option<String>,
+    config_file: Option<PathBuf>,
+    ggir_version: Option<String>,
+    dataset: Option<String>,
+    lookup: Option<LookupMode>,
+    variant: Option<String>,
+    all_variants: bool,
+    verbose: bool,
+    // Profile from `--profile` or REPORT_BUILDER_PROFILE.
+    profile: Option<String>,
+    // Set when `profile` came from REPORT_BUILDER_PROFILE, which is skipped rather than fatal
+    // when no config file defines that profile.
+    profile_from_env: bool,
+}
+
+/// Everything a run needs from the command line, environment and config file.
+#[derive(Clone)]
+struct Settings {
+    share_path: PathBuf,
+    // Where the share path came from, e.g. "--share-path flag".
+    share_source: String,
+    // Roots tried in order when `share_path` is unreachable or lacks a subject.
+    fallback_roots: Vec<PathBuf>,
+    layout: String,
+    // A pinned GGIR version; `None` picks the newest version found for each subject.
+    ggir_version: Option<String>,
+    // A dataset folder or pattern from `--dataset`, used instead of the study table's dataset.
+    dataset: Option<DatasetOverride>,
+    lookup: LookupMode,
+    subject_rules: SubjectRules,
+    // File name pattern for the part5 day summary, matched case-insensitively.
+    part5_pattern: String,
+    // Summarize each matching variant rather than requiring exactly one.
+    all_variants: bool,
+    // Configured header aliases keyed by required column name.
+    column_aliases: BTreeMap<String, Vec<String>>,
+    verbose: bool,
+    // The subject index scanned during this run, shared by every copy of these settings so the
+    // share is walked at most once per process.
+    scanned_index: Rc<OnceCell<SubjectIndex>>,
+}
+
+/// A `--dataset` value, limited to one study when given as `STUDY=NAME`.
+#[derive(Clone)]
+struct DatasetOverride {
+    // `None` applies the dataset to whichever study the subjects belong to.
+    study: Option<String>,
+    dataset: String,
+}
+
+impl DatasetOverride {
+    /// Whether a scanned match is in this dataset, and in this study when one is named.
+    fn selects(&self, found: &SubjectMatch) -> bool {
+        self.study
+            .as_ref()
+            .map_or(true, |study| *study == found.study)
+            && wildcard_match(&self.dataset, &found.dataset)
+    }
+}
+
+/// The single table used both to validate subject numbers and to find their study folder.
+#[derive(Clone)]
+struct SubjectRules {
+    digits: usize,
+    studies: Vec<StudyRule>,
 }
 
 #[allow(dead_code)]
 struct Session {
     share_path: PathBuf,
     subject_number: String,
-    subject_directory: PathBuf,
+    location: SubjectLocation,
+    // Parameters of the part5 variant that was read; `None` when nothing matched.
+    part5: Option<Part5Params>,
+    csv_files: Vec<PathBuf>,
+    // Problems found while locating and reading the subject, kept for whoever displays the session.
+    warnings: Vec<String>,
     // Precomputed day-level metrics keyed by participant ID.
     activity_data: HashMap<String, Vec<DayMetrics>>,
     weekly_summary: Option<WeeklySummary>,
 }
 
+/// Where a subject's folder was found and the choices made while resolving it.
+#[derive(Debug, Clone)]
+struct SubjectLocation {
+    // The share root the folder was found under.
+    share_root: PathBuf,
+    directory: PathBuf,
+    study: String,
+    dataset: String,
+    // Other datasets of the study that also hold this subject, newest first.
+    other_datasets: Vec<String>,
+    // Datasets of the study newer than `dataset` that lack this subject, newest first.
+    newer_datasets: Vec<String>,
+    ggir_version: Option<String>,
+    // Other GGIR versions that also hold output for this subject, newest first.
+    other_ggir_versions: Vec<String>,
+}
+
 #[derive(Debug, Clone)]
 struct DayMetrics {
     id: String,
@@ -67,296 +732,3619 @@ struct WeeklySummary {
     average_sleep_by_weekday: Vec<(Weekday, f64)>,
 }
 
-fn main() {
-    let cli = Cli::parse();
+fn main() {
+    let cli = Cli::parse();
+    let profile_name = |name: String| Some(name.trim().to_string()).filter(|name| !name.is_empty());
+    let profile_flag = cli.profile.and_then(profile_name);
+    let profile_env = std::env::var(PROFILE_ENV).ok().and_then(profile_name);
+    let overrides = ConfigOverrides {
+        share_path: cli.share_path,
+        config_file: cli.config,
+        ggir_version: cli.ggir_version,
+        dataset: cli.dataset,
+        lookup: cli.lookup,
+        variant: cli.variant,
+        all_variants: cli.all_variants,
+        verbose: cli.verbose,
+        profile_from_env: profile_flag.is_none() && profile_env.is_some(),
+        profile: profile_flag.or(profile_env),
+    };
+
+    let result = match cli.command {
+        Some(Commands::Init { force }) => handle_init(&overrides, force),
+        Some(Commands::Config { action }) => handle_config(&overrides, &action),
+        Some(Commands::Explain { subject }) => handle_explain(&overrides, &subject),
+        Some(Commands::Tui) => handle_tui(&overrides),
+        Some(Commands::Completions { shell }) => handle_completions(shell),
+        Some(Commands::Manpage) => handle_manpage(),
+        Some(Commands::Report { subjects, format }) => handle_report(&overrides, &subjects, format),
+        Some(Commands::Batch {
+            file,
+            range,
+            format,
+            output,
+        }) => handle_batch(
+            &overrides,
+            file.as_deref(),
+            range.as_deref(),
+            format,
+            output.as_deref(),
+        ),
+        Some(Commands::Discover { format, output }) => {
+            handle_discover(&overrides, format, output.as_deref())
+        }
+        Some(Commands::Sync {
+            subjects,
+            file,
+            range,
+            verify,
+        }) => handle_sync(
+            &overrides,
+            &subjects,
+            file.as_deref(),
+            range.as_deref(),
+            verify,
+        ),
+        None => run_interactive(&overrides),
+    };
+
+    if let Err(err) = result {
+        match cli.error_format {
+            ErrorFormat::Text => eprintln!("Error: {}", err),
+            ErrorFormat::Json => eprintln!("{}", err.to_json()),
+        }
+        std::process::exit(err.exit_code());
+    }
+}
+
+fn handle_init(overrides: &ConfigOverrides, force: bool) -> AppResult<()> {
+    let provided = overrides
+        .share_path
+        .as_deref()
+        .map(str::trim)
+        .filter(|path| !path.is_empty());
+
+    let config_file = config_file_path(overrides)?;
+    // Re-running init only replaces the share path; other keys are kept.
+    let mut config = if config_file.exists() {
+        load_config(&config_file)?
+    } else {
+        Config::default()
+    };
+    let profile = overrides.profile.as_deref();
+    // A profile's own layout decides what a valid share looks like for it; otherwise the layout
+    // in effect from every layer does.
+    let layered = load_layered_config(overrides, None)?;
+    let layout = profile
+        .and_then(|name| layered.config.profiles.as_ref()?.get(name)?.layout.clone())
+        .or(layered.config.layout)
+        .unwrap_or_else(|| DEFAULT_LAYOUT.to_string());
+
+    let share_path = match provided {
+        Some(path) => {
+            if let Err(reason) = check_share_layout(Path::new(path), &layout) {
+                if !force {
+                    return Err(AppError::ShareUnreachable {
+                        path: PathBuf::from(path),
+                        reason: format!("{} Pass --force to save it anyway.", reason),
+                    });
+                }
+                println!("Warning: {} Saving anyway.", reason);
+            }
+            path.to_string()
+        }
+        None => prompt_for_checked_share_path(&layout, force)?,
+    };
+
+    // Init with a profile creates it if needed and leaves the top-level keys alone.
+    let target = match profile {
+        Some(name) => config
+            .profiles
+            .get_or_insert_with(BTreeMap::new)
+            .entry(name.to_string())
+            .or_default(),
+        None => &mut config,
+    };
+    // A per-OS table only has this platform's entry replaced.
+    target.share_path = Some(match target.share_path.take() {
+        Some(SharePathSetting::PerOs(mut paths)) => {
+            paths.insert(std::env::consts::OS.to_string(), share_path);
+            SharePathSetting::PerOs(paths)
+        }
+        _ => SharePathSetting::Single(share_path),
+    });
+    save_config(&config_file, &config)?;
+
+    match profile {
+        Some(name) => println!(
+            "Saved vosslabhpc share path for profile {} to {}",
+            name,
+            config_file.display()
+        ),
+        None => println!("Saved vosslabhpc share path to {}", config_file.display()),
+    }
+
+    Ok(())
+}
+
+/// Prompts until the share path passes the layout check or the user chooses to keep it anyway.
+fn prompt_for_checked_share_path(layout: &str, force: bool) -> AppResult<String> {
+    loop {
+        let share_path = prompt_for_share_path()?;
+
+        let reason = match check_share_layout(Path::new(&share_path), layout) {
+            Ok(()) => return Ok(share_path),
+            Err(reason) => reason,
+        };
+
+        if force {
+            println!("Warning: {} Saving anyway.", reason);
+            return Ok(share_path);
+        }
+
+        println!("{}", reason);
+        loop {
+            println!("[r]etry, [f]orce save, or [q]uit?");
+            print!("> ");
+            io::stdout().flush()?;
+
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+
+            match input.trim().to_lowercase().as_str() {
+                "r" | "retry" => break,
+                "f" | "force" => return Ok(share_path),
+                "q" | "quit" => {
+                    return Err(AppError::ShareUnreachable {
+                        path: PathBuf::from(share_path),
+                        reason: reason.clone(),
+                    })
+                }
+                _ => println!("Please enter r, f, or q."),
+            }
+        }
+    }
+}
+
+/// Checks that a share path exists and contains the fixed folders at the start of the layout
+/// (Projects/BOOST for the default layout).
+fn check_share_layout(share_path: &Path, layout: &str) -> Result<(), String> {
+    if !share_path.is_dir() {
+        return Err(format!(
+            "{} does not exist or is not a directory. Is the share mounted?",
+            share_path.display()
+        ));
+    }
+
+    let segments = layout_segments(layout);
+    if segments.first() != Some(&"{share}") {
+        return Ok(());
+    }
+
+    let fixed: Vec<&str> = segments[1..]
+        .iter()
+        .take_while(|segment| !segment.contains('{'))
+        .copied()
+        .collect();
+    let expected = fixed
+        .iter()
+        .fold(share_path.to_path_buf(), |path, segment| path.join(segment));
+    if !expected.is_dir() {
+        return Err(format!(
+            "{} does not contain the expected {} folder.",
+            share_path.display(),
+            fixed.join("/")
+        ));
+    }
+
+    Ok(())
+}
+
+fn prompt_for_share_path() -> Result<String, io::Error> {
+    let example_path = example_share_path();
+
+    loop {
+        println!(
+            "Enter the path to the vosslabhpc share (e.g., {}):",
+            example_path
+        );
+        print!("> ");
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        let trimmed = input.trim();
+
+        if trimmed.is_empty() {
+            println!("A value is required. Please try again.");
+            continue;
+        }
+
+        return Ok(trimmed.to_string());
+    }
+}
+
+fn determine_config_dir() -> AppResult<PathBuf> {
+    let project = ProjectDirs::from("com", "hbc", "report-builder")
+        .ok_or("Unable to determine the user's configuration directory.")?;
+    Ok(project.config_dir().to_path_buf())
+}
+
+fn determine_data_dir() -> AppResult<PathBuf> {
+    let project = ProjectDirs::from("com", "hbc", "report-builder")
+        .ok_or("Unable to determine the user's data directory.")?;
+    Ok(project.data_dir().to_path_buf())
+}
+
+fn determine_cache_dir() -> AppResult<PathBuf> {
+    let project = ProjectDirs::from("com", "hbc", "report-builder")
+        .ok_or("Unable to determine the user's cache directory.")?;
+    Ok(project.cache_dir().to_path_buf())
+}
+
+/// Rewrites a configured path with this platform's separators, so `//vosslabhpc/Projects`
+/// and `\\vosslabhpc\Projects` name the same share everywhere.
+fn native_path(path: &str) -> PathBuf {
+    if cfg!(target_os = "windows") {
+        PathBuf::from(path.replace('/', "\\"))
+    } else {
+        PathBuf::from(path.replace('\\', "/"))
+    }
+}
+
+fn example_share_path() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "/Volumes/vosslabhpc"
+    } else if cfg!(target_os = "windows") {
+        r"\\vosslabhpc"
+    } else {
+        "/mnt/vosslabhpc"
+    }
+}
+
+fn run_interactive(overrides: &ConfigOverrides) -> AppResult<()> {
+    let settings = resolve_settings(overrides)?;
+    // Each prompt covers one subject, so only scanning can put several studies in play.
+    settings.check_dataset_scope(Some(&[]))?;
+
+    println!("{}", settings.share_summary());
+
+    // Every subject summarized during this sitting, in the order first processed.
+    let mut results: Vec<Session> = Vec::new();
+    let mut unexported = false;
+    let Some(mut subject_number) = prompt_for_subject_number(&settings.subject_rules)? else {
+        return Ok(());
+    };
+
+    loop {
+        match summarize_interactively(&settings, &subject_number) {
+            Ok(sessions) => {
+                for session in sessions {
+                    match results.iter_mut().find(|existing| {
+                        // Variants without parameters are told apart by their files.
+                        existing.subject_number == session.subject_number
+                            && existing.part5 == session.part5
+                            && (session.part5.is_some() || existing.csv_files == session.csv_files)
+                    }) {
+                        Some(existing) => *existing = session,
+                        None => results.push(session),
+                    }
+                    unexported = true;
+                }
+            }
+            // A bad subject should not end the sitting; report it and offer the menu.
+            Err(err) => println!("Error: {}", err),
+        }
+
+        loop {
+            match prompt_for_next_action(results.len())? {
+                NextAction::Next => {
+                    // Input ended, so nobody is left to answer an export prompt.
+                    let Some(next) = prompt_for_subject_number(&settings.subject_rules)? else {
+                        return Ok(());
+                    };
+                    subject_number = next;
+                    break;
+                }
+                NextAction::Rerun => break,
+                NextAction::Export => match export_results(&results) {
+                    Ok(written) => unexported &= !written,
+                    Err(err) => println!("Error: {}", err),
+                },
+                NextAction::Quit => {
+                    if unexported
+                        && prompt_yes_no(&format!(
+                            "Export {} result(s) from this session before quitting?",
+                            results.len()
+                        ))?
+                    {
+                        export_results(&results)?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Runs and prints the pipeline for one subject, asking which part5 variant to use when several
+/// match; returns the sessions that found target files.
+fn summarize_interactively(settings: &Settings, subject_number: &str) -> AppResult<Vec<Session>> {
+    let location = match build_subject_directory(settings, subject_number) {
+        Err(AppError::AmbiguousSubject { matches, .. }) => {
+            match prompt_for_match(subject_number, &matches)? {
+                Some(found) => locate_match(settings, subject_number, found),
+                None => return Ok(Vec::new()),
+            }
+        }
+        result => result?,
+    };
+    let (location, variants) = find_variants(settings, location)?;
+
+    let chosen: Vec<Option<Part5Variant>> = if variants.is_empty() {
+        vec![None]
+    } else if variants.len() > 1 && !settings.all_variants {
+        prompt_for_variants(subject_number, variants)?
+            .into_iter()
+            .map(Some)
+            .collect()
+    } else {
+        variants.into_iter().map(Some).collect()
+    };
+
+    let mut sessions = Vec::new();
+    for variant in chosen {
+        let session = read_session(settings, subject_number, location.clone(), variant)?;
+        if print_interactive_session(&session) {
+            sessions.push(session);
+        }
+    }
+    Ok(sessions)
+}
+
+/// Prints one session the way the interactive mode shows it; `false` means no target files.
+fn print_interactive_session(session: &Session) -> bool {
+    session.print_warnings();
+
+    println!(
+        "Located {} target file(s) for subject {} under {}",
+        session.csv_files.len(),
+        session.subject_number,
+        session.location.directory.display()
+    );
+    println!(
+        "Found in dataset {} of {} on {}.",
+        session.location.dataset,
+        session.location.study,
+        session.share_path.display()
+    );
+    if let Some(version) = &session.location.ggir_version {
+        println!("Using GGIR {} output.", version);
+    }
+    if let Some(params) = &session.part5 {
+        println!("Part5 variant: {}", params.describe());
+    }
+
+    if session.csv_files.is_empty() {
+        println!("No matching files found; verify the subject data is available.");
+        return false;
+    }
+
+    for path in &session.csv_files {
+        println!("  {}", path.display());
+    }
+
+    println!(
+        "Prepared metrics for {} participant(s).",
+        session.activity_data.len()
+    );
+
+    for (id, records) in session.activity_data.iter().take(5) {
+        println!("  {} -> {} day(s) of data", id, records.len());
+    }
+    if session.activity_data.len() > 5 {
+        println!("  ...");
+    }
+
+    print_weekly_summary(session.weekly_summary.as_ref());
+
+    println!(
+        "Session ready with {} total day-level rows for downstream aggregation.",
+        session.total_rows()
+    );
+
+    true
+}
+
+/// Lists every study dataset holding the subject and lets the user pick one.
+fn prompt_for_match<'a>(
+    subject_number: &str,
+    matches: &'a [SubjectMatch],
+) -> Result<Option<&'a SubjectMatch>, io::Error> {
+    println!(
+        "Subject {} was found in {} places:",
+        subject_number,
+        matches.len()
+    );
+    for (index, found) in matches.iter().enumerate() {
+        println!("  [{}] {}", index + 1, found);
+    }
+
+    loop {
+        print!("Location number: ");
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Ok(None);
+        }
+
+        match input.trim().parse::<usize>() {
+            Ok(number) if (1..=matches.len()).contains(&number) => {
+                return Ok(matches.get(number - 1))
+            }
+            _ => println!("Please enter a number from 1 to {}.", matches.len()),
+        }
+    }
+}
+
+/// Lists the matching part5 variants and lets the user pick one of them or all.
+fn prompt_for_variants(
+    subject_number: &str,
+    variants: Vec<Part5Variant>,
+) -> Result<Vec<Part5Variant>, io::Error> {
+    println!(
+        "Subject {} has {} part5 variants:",
+        subject_number,
+        variants.len()
+    );
+    for (index, variant) in variants.iter().enumerate() {
+        println!("  [{}] {}", index + 1, variant.describe());
+    }
+
+    loop {
+        print!("Variant number, or [a]ll: ");
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Ok(Vec::new());
+        }
+
+        let answer = input.trim().to_lowercase();
+        if answer == "a" || answer == "all" {
+            return Ok(variants);
+        }
+        match answer.parse::<usize>() {
+            Ok(number) if (1..=variants.len()).contains(&number) => {
+                return Ok(variants.into_iter().skip(number - 1).take(1).collect())
+            }
+            _ => println!("Please enter a number from 1 to {} or a.", variants.len()),
+        }
+    }
+}
+
+enum NextAction {
+    Next,
+    Rerun,
+    Export,
+    Quit,
+}
+
+fn prompt_for_next_action(result_count: usize) -> Result<NextAction, io::Error> {
+    loop {
+        println!();
+        println!(
+            "[n]ext subject, [r]e-run, [e]xport {} result(s), or [q]uit?",
+            result_count
+        );
+        print!("> ");
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Ok(NextAction::Quit);
+        }
+
+        match input.trim().to_lowercase().as_str() {
+            "n" | "next" => return Ok(NextAction::Next),
+            "r" | "rerun" | "re-run" => return Ok(NextAction::Rerun),
+            "e" | "export" => return Ok(NextAction::Export),
+            "q" | "quit" => return Ok(NextAction::Quit),
+            _ => println!("Please enter n, r, e, or q."),
+        }
+    }
+}
+
+fn prompt_yes_no(question: &str) -> Result<bool, io::Error> {
+    loop {
+        println!("{} [y/n]", question);
+        print!("> ");
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Ok(false);
+        }
+
+        match input.trim().to_lowercase().as_str() {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please enter y or n."),
+        }
+    }
+}
+
+/// Writes the session's results as a cohort CSV at a path chosen by the user; returns whether
+/// anything was written.
+fn export_results(results: &[Session]) -> AppResult<bool> {
+    const DEFAULT_EXPORT: &str = "report-builder-results.csv";
+
+    if results.is_empty() {
+        println!("No results to export yet.");
+        return Ok(false);
+    }
+
+    println!("Export file (default: {}):", DEFAULT_EXPORT);
+    print!("> ");
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input)? == 0 {
+        println!("Export cancelled.");
+        return Ok(false);
+    }
+    let path = match input.trim() {
+        "" => PathBuf::from(DEFAULT_EXPORT),
+        value => PathBuf::from(value),
+    };
+
+    if path.exists()
+        && !prompt_yes_no(&format!("{} already exists. Overwrite it?", path.display()))?
+    {
+        println!("Export cancelled.");
+        return Ok(false);
+    }
+
+    let rows = cohort_rows(results);
+    fs::write(&path, render_csv(&cohort_headers(), &rows)?)?;
+    println!("Exported {} subject(s) to {}", rows.len(), path.display());
+
+    Ok(true)
+}
+
+fn handle_report(
+    overrides: &ConfigOverrides,
+    subjects: &[String],
+    format: OutputFormat,
+) -> AppResult<()> {
+    let settings = resolve_settings(overrides)?;
+
+    for subject in subjects {
+        settings
+            .subject_rules
+            .validate(subject)
+            .map_err(|reason| AppError::InvalidSubject {
+                subject: subject.clone(),
+                reason,
+            })?;
+    }
+    settings.check_dataset_scope(Some(subjects))?;
+
+    // Keep stdout clean for machine-readable output.
+    eprintln!("{}", settings.share_summary());
+
+    let mut sessions = Vec::with_capacity(subjects.len());
+    for subject in subjects {
+        sessions.extend(summarize_subject(&settings, subject)?);
+    }
+
+    match format {
+        OutputFormat::Text => {
+            for (index, session) in sessions.iter().enumerate() {
+                if index > 0 {
+                    println!();
+                }
+                println!(
+                    "Subject {} ({} file(s), {} day-level row(s))",
+                    session.subject_number,
+                    session.csv_files.len(),
+                    session.total_rows()
+                );
+                println!("Share root: {}", session.share_path.display());
+                println!(
+                    "Dataset: {} ({})",
+                    session.location.dataset, session.location.study
+                );
+                if let Some(version) = &session.location.ggir_version {
+                    println!("GGIR version: {}", version);
+                }
+                if let Some(params) = &session.part5 {
+                    println!("Part5 variant: {}", params.describe());
+                }
+                print_weekly_summary(session.weekly_summary.as_ref());
+            }
+        }
+        OutputFormat::Json => {
+            let reports: Vec<Value> = sessions.iter().map(session_to_json).collect();
+            println!("{}", serde_json::to_string_pretty(&reports)?);
+        }
+    }
+
+    Ok(())
+}
+
+struct BatchFailure {
+    subject: String,
+    error: AppError,
+}
+
+fn handle_batch(
+    overrides: &ConfigOverrides,
+    file: Option<&Path>,
+    range: Option<&str>,
+    format: TableFormat,
+    output: Option<&Path>,
+) -> AppResult<()> {
+    let settings = resolve_settings(overrides)?;
+
+    let subjects = match (file, range) {
+        (Some(file), _) => read_subject_list(file)?,
+        (None, Some(range)) => expand_subject_range(&settings.subject_rules, range)?,
+        (None, None) => return Err("Provide either --file or --range.".into()),
+    };
+
+    if subjects.is_empty() {
+        return Err("The subject list is empty.".into());
+    }
+    settings.check_dataset_scope(Some(&subjects))?;
+
+    eprintln!("{}", settings.share_summary());
+    eprintln!("Processing {} subject(s)...", subjects.len());
+
+    let mut sessions = Vec::new();
+    let mut failures = Vec::new();
+    // Table rows follow the subject list; skipped subjects stay in them, not only on stderr.
+    let mut rows = Vec::new();
+
+    for subject in &subjects {
+        match summarize_subject(&settings, subject) {
+            Ok(subject_sessions) => {
+                rows.extend(cohort_rows(&subject_sessions));
+                sessions.extend(subject_sessions);
+            }
+            Err(error) => {
+                let failure = BatchFailure {
+                    subject: subject.clone(),
+                    error,
+                };
+                rows.push(failure_row(&failure));
+                failures.push(failure);
+            }
+        }
+    }
+
+    let rendered = match format {
+        TableFormat::Table => render_table(&cohort_headers(), &rows),
+        TableFormat::Csv => render_csv(&cohort_headers(), &rows)?,
+        TableFormat::Json => {
+            let results: Vec<Value> = sessions.iter().map(session_to_json).collect();
+            let skipped: Vec<Value> = failures
+                .iter()
+                .map(|failure| {
+                    json!({
+                        "subject": failure.subject,
+                        "kind": failure.error.kind(),
+                        "reason": failure.error.to_string(),
+                    })
+                })
+                .collect();
+            let mut text =
+                serde_json::to_string_pretty(&json!({ "results": results, "failures": skipped }))?;
+            text.push('\n');
+            text
+        }
+    };
+
+    match output {
+        Some(path) => {
+            fs::write(path, rendered)?;
+            eprintln!("Wrote cohort results to {}", path.display());
+        }
+        None => print!("{}", rendered),
+    }
+
+    eprintln!(
+        "Summarized {} of {} subject(s).",
+        subjects.len() - failures.len(),
+        subjects.len()
+    );
+    if !failures.is_empty() {
+        eprintln!("Skipped {} subject(s):", failures.len());
+        for failure in &failures {
+            eprintln!("  {}: {}", failure.subject, failure.error);
+        }
+    }
+
+    if sessions.is_empty() {
+        return Err(AppError::BatchFailed {
+            failed: failures.len(),
+        });
+    }
+
+    Ok(())
+}
+
+/// One subject folder found by `discover`, with one of the part5 variants in it if any.
+struct InventoryRow {
+    subject: String,
+    study: String,
+    dataset: String,
+    ggir_version: Option<String>,
+    directory: PathBuf,
+    variant: Option<Part5Variant>,
+    // Distinct dates among the rows a report keeps; `None` when a file could not be read.
+    days: Option<usize>,
+    modified: Option<String>,
+}
+
+fn handle_discover(
+    overrides: &ConfigOverrides,
+    format: TableFormat,
+    output: Option<&Path>,
+) -> AppResult<()> {
+    let settings = resolve_settings(overrides)?;
+    settings.check_dataset_scope(None)?;
+    eprintln!("{}", settings.share_summary());
+
+    let rows: Vec<InventoryRow> = settings
+        .share_roots()
+        .into_iter()
+        .flat_map(|root| build_inventory(&settings.with_share_root(root)))
+        .collect();
+
+    let headers: Vec<String> = [
+        "subject",
+        "study",
+        "dataset",
+        "ggir_version",
+        "part5",
+        "variant",
+        "days",
+        "modified",
+        "directory",
+    ]
+    .iter()
+    .map(|header| header.to_string())
+    .collect();
+    let table_rows = || -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| {
+                vec![
+                    row.subject.clone(),
+                    row.study.clone(),
+                    row.dataset.clone(),
+                    row.ggir_version.clone().unwrap_or_default(),
+                    if row.variant.is_some() { "yes" } else { "no" }.to_string(),
+                    row.variant
+                        .as_ref()
+                        .map(|variant| variant.name.clone())
+                        .unwrap_or_default(),
+                    row.days.map(|days| days.to_string()).unwrap_or_default(),
+                    row.modified.clone().unwrap_or_default(),
+                    row.directory.display().to_string(),
+                ]
+            })
+            .collect()
+    };
+
+    let rendered = match format {
+        TableFormat::Table => render_table(&headers, &table_rows()),
+        TableFormat::Csv => render_csv(&headers, &table_rows())?,
+        TableFormat::Json => {
+            let entries: Vec<Value> = rows
+                .iter()
+                .map(|row| {
+                    json!({
+                        "subject": row.subject,
+                        "study": row.study,
+                        "dataset": row.dataset,
+                        "ggir_version": row.ggir_version,
+                        "directory": row.directory.display().to_string(),
+                        "part5": row.variant.as_ref().map(|variant| json!({
+                            "name": variant.name,
+                            "parameters": variant.params.as_ref().map(Part5Params::to_json),
+                            "files": variant
+                                .files
+                                .iter()
+                                .map(|path| path.display().to_string())
+                                .collect::<Vec<_>>(),
+                        })),
+                        "days": row.days,
+                        "modified": row.modified,
+                    })
+                })
+                .collect();
+            let mut text = serde_json::to_string_pretty(&entries)?;
+            text.push('\n');
+            text
+        }
+    };
+
+    match output {
+        Some(path) => {
+            fs::write(path, rendered)?;
+            eprintln!("Wrote inventory to {}", path.display());
+        }
+        None => print!("{}", rendered),
+    }
+
+    let with_part5 = rows.iter().filter(|row| row.variant.is_some()).count();
+    eprintln!(
+        "Found {} subject folder row(s), {} with a part5 day summary.",
+        rows.len(),
+        with_part5
+    );
+
+    Ok(())
+}
+
+/// Walks every configured study, dataset and GGIR version under `settings.share_path` and
+/// describes each subject folder.
+fn build_inventory(settings: &Settings) -> Vec<InventoryRow> {
+    let segments = layout_segments(&settings.layout);
+    let Some(subject_index) = segments
+        .iter()
+        .position(|segment| segment.contains("{subject}"))
+    else {
+        return Vec::new();
+    };
+    let uses_versions = settings.layout.contains("{ggir_version}");
+
+    let mut rows = Vec::new();
+    for rule in &settings.subject_rules.studies {
+        let datasets = candidate_datasets(settings, &segments, rule);
+
+        for dataset in &datasets {
+            let base_values = [
+                ("study", rule.study.as_str()),
+                ("dataset", dataset.as_str()),
+            ];
+            let versions = if uses_versions {
+                ggir_versions_in(settings, &segments, &base_values)
+            } else {
+                vec![DEFAULT_GGIR_VERSION.to_string()]
+            };
+
+            for version in &versions {
+                let mut values = base_values.to_vec();
+                values.push(("ggir_version", version.as_str()));
+
+                for (subject, directory) in
+                    subject_folders_at(settings, &segments, subject_index, &values)
+                {
+                    let variants = discover_part5_variants(&directory, &settings.part5_pattern);
+                    let aliases =
+                        settings.column_aliases(uses_versions.then_some(version.as_str()));
+                    let entry = |variant: Option<Part5Variant>| {
+                        let (days, modified) = match &variant {
+                            Some(variant) => (
+                                count_days(&variant.files, &aliases),
+                                latest_modification(&variant.files),
+                            ),
+                            None => (None, None),
+                        };
+                        InventoryRow {
+                            subject: subject.clone(),
+                            study: rule.study.clone(),
+                            dataset: dataset.clone(),
+                            ggir_version: uses_versions.then(|| version.clone()),
+                            directory: directory.clone(),
+                            variant,
+                            days,
+                            modified,
+                        }
+                    };
+
+                    if variants.is_empty() {
+                        rows.push(entry(None));
+                    } else {
+                        rows.extend(variants.into_iter().map(|variant| entry(Some(variant))));
+                    }
+                }
+            }
+        }
+    }
+    rows
+}
+
+/// Counts the distinct calendar dates among the rows a report would keep from the given CSV
+/// files; `None` if any of them cannot be read or lacks a required column.
+fn count_days(files: &[PathBuf], aliases: &ColumnAliases) -> Option<usize> {
+    let mut warnings = Vec::new();
+    let matrix = collect_activity_metrics(files, aliases, false, &mut warnings).ok()?;
+    let dates: BTreeSet<String> = matrix
+        .values()
+        .flatten()
+        .map(|day| {
+            parse_calendar_date(&day.calendar_date)
+                .map_or_else(|| day.calendar_date.clone(), |date| date.to_string())
+        })
+        .collect();
+    Some(dates.len())
+}
+
+/// The most recent modification time among the files, formatted in UTC.
+fn latest_modification(files: &[PathBuf]) -> Option<String> {
+    files
+        .iter()
+        .filter_map(|file| fs::metadata(file).and_then(|meta| meta.modified()).ok())
+        .max()
+        .map(|time| {
+            DateTime::<Utc>::from(time)
+                .format("%Y-%m-%d %H:%M UTC")
+                .to_string()
+        })
+}
+
+/// Where `sync` copies share files, laid out exactly like the share.
+fn synced_root() -> AppResult<PathBuf> {
+    Ok(determine_data_dir()?.join("share"))
+}
+
+/// What `sync` recorded about each cached file, keyed by its path relative to `synced_root`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct SyncManifest {
+    files: BTreeMap<String, CachedFile>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct CachedFile {
+    subject: String,
+    source: PathBuf,
+    sha256: String,
+    size: u64,
+    source_modified: String,
+}
+
+fn handle_sync(
+    overrides: &ConfigOverrides,
+    subjects: &[String],
+    file: Option<&Path>,
+    range: Option<&str>,
+    verify: bool,
+) -> AppResult<()> {
+    let mut settings = resolve_settings(overrides)?;
+    let cache_root = synced_root()?;
+    // Syncing must read the share itself, never the cache it is refreshing.
+    settings.fallback_roots.retain(|root| *root != cache_root);
+
+    let mut selected: Vec<String> = subjects.to_vec();
+    if let Some(file) = file {
+        selected.extend(read_subject_list(file)?);
+    }
+    if let Some(range) = range {
+        selected.extend(expand_subject_range(&settings.subject_rules, range)?);
+    }
+    // A subject can come from the arguments, the file and the range alike.
+    selected.sort();
+    selected.dedup();
+
+    let manifest_file = determine_data_dir()?.join("sync-manifest.json");
+    let mut manifest = load_manifest(&manifest_file)?;
+
+    if verify {
+        return verify_cache(&cache_root, &manifest, &selected);
+    }
+    if selected.is_empty() {
+        return Err("Provide subject numbers, --file, or --range.".into());
+    }
+    settings.check_dataset_scope(Some(&selected))?;
+
+    eprintln!("{}", settings.share_summary());
+    let mut copied = 0;
+    let mut unchanged = 0;
+    let mut failures = Vec::new();
+
+    for subject in &selected {
+        match sync_subject(&settings, subject, &cache_root, &mut manifest) {
+            Ok((subject_copied, subject_unchanged)) => {
+                copied += subject_copied;
+                unchanged += subject_unchanged;
+            }
+            Err(error) => failures.push(BatchFailure {
+                subject: subject.clone(),
+                error,
+            }),
+        }
+    }
+
+    save_manifest(&manifest_file, &manifest)?;
+    eprintln!(
+        "Copied {} file(s), {} already up to date, into {}.",
+        copied,
+        unchanged,
+        cache_root.display()
+    );
+    if !failures.is_empty() {
+        eprintln!("Skipped {} subject(s):", failures.len());
+        for failure in &failures {
+            eprintln!("  {}: {}", failure.subject, failure.error);
+        }
+    }
+
+    if failures.len() == selected.len() {
+        return Err(AppError::BatchFailed {
+            failed: failures.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Copies every part5 file matching the configured pattern for one subject; returns how many
+/// files were copied and how many were already current.
+fn sync_subject(
+    settings: &Settings,
+    subject: &str,
+    cache_root: &Path,
+    manifest: &mut SyncManifest,
+) -> AppResult<(usize, usize)> {
+    settings
+        .subject_rules
+        .validate(subject)
+        .map_err(|reason| AppError::InvalidSubject {
+            subject: subject.to_string(),
+            reason,
+        })?;
+
+    let (location, variants) = find_subject(settings, subject)?;
+    if variants.is_empty() {
+        return Err(AppError::NoCsvFound {
+            subject: subject.to_string(),
+            directory: location.directory,
+        });
+    }
+
+    let (mut copied, mut unchanged) = (0, 0);
+    for source in variants.iter().flat_map(|variant| &variant.files) {
+        let relative = source.strip_prefix(&location.share_root).map_err(|_| {
+            AppError::Other(format!(
+                "{} is not under {}",
+                source.display(),
+                location.share_root.display()
+            ))
+        })?;
+        let key = relative.to_string_lossy().replace('\\', "/");
+        let target = cache_root.join(relative);
+
+        let metadata = fs::metadata(source)?;
+        let modified = format_modified(metadata.modified()?);
+        if let Some(cached) = manifest.files.get(&key) {
+            if cached.source_modified == modified
+                && cached.size == metadata.len()
+                && target.is_file()
+            {
+                unchanged += 1;
+                continue;
+            }
+        }
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(source, &target)?;
+        manifest.files.insert(
+            key,
+            CachedFile {
+                subject: subject.to_string(),
+                source: source.clone(),
+                sha256: sha256_file(&target)?,
+                size: metadata.len(),
+                source_modified: modified,
+            },
+        );
+        eprintln!("Cached {}", source.display());
+        copied += 1;
+    }
+
+    Ok((copied, unchanged))
+}
+
+/// Compares cached files with the share and with their recorded hashes.
+fn verify_cache(cache_root: &Path, manifest: &SyncManifest, subjects: &[String]) -> AppResult<()> {
+    let mut checked = 0;
+    let mut stale = 0;
+
+    for (key, cached) in &manifest.files {
+        if !subjects.is_empty() && !subjects.contains(&cached.subject) {
+            continue;
+        }
+        checked += 1;
+
+        let cached_hash = sha256_file(&cache_root.join(key)).ok();
+        let status = if cached_hash.as_deref() != Some(cached.sha256.as_str()) {
+            "cached copy missing or damaged"
+        } else if !cached.source.is_file() {
+            "not reachable on the share"
+        } else if sha256_file(&cached.source)? != cached.sha256 {
+            "changed on the share"
+        } else {
+            "ok"
+        };
+
+        if status != "ok" && status != "not reachable on the share" {
+            stale += 1;
+        }
+        println!(
+            "{}  {}  {}",
+            cached.subject,
+            status,
+            cached.source.display()
+        );
+    }
+
+    eprintln!("Checked {} cached file(s); {} out of date.", checked, stale);
+    if stale > 0 {
+        return Err(AppError::CacheStale { stale });
+    }
+    Ok(())
+}
+
+fn load_manifest(manifest_file: &Path) -> AppResult<SyncManifest> {
+    if !manifest_file.exists() {
+        return Ok(SyncManifest::default());
+    }
+    let contents = fs::read_to_string(manifest_file)?;
+    serde_json::from_str(&contents).map_err(|err| {
+        AppError::Other(format!(
+            "Failed to read sync manifest {}: {}",
+            manifest_file.display(),
+            err
+        ))
+    })
+}
+
+fn save_manifest(manifest_file: &Path, manifest: &SyncManifest) -> AppResult<()> {
+    if let Some(parent) = manifest_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(manifest_file, serde_json::to_string_pretty(manifest)?)?;
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut file = fs::File::open(path)?;
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn format_modified(time: std::time::SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339()
+}
+
+/// Runs the full pipeline for one subject, turning every reason to skip it into an error.
+fn summarize_subject(settings: &Settings, subject: &str) -> AppResult<Vec<Session>> {
+    settings
+        .subject_rules
+        .validate(subject)
+        .map_err(|reason| AppError::InvalidSubject {
+            subject: subject.to_string(),
+            reason,
+        })?;
+
+    // With --all-variants one unusable variant should not cost the subject the others.
+    let mut sessions = Vec::new();
+    let mut failures = Vec::new();
+    let location = build_subject_directory(settings, subject)?;
+    for result in build_variant_sessions(settings, subject, location)? {
+        let session = match result {
+            Ok(session) => session,
+            Err(error) => {
+                failures.push((None, error));
+                continue;
+            }
+        };
+        session.print_warnings();
+
+        let variant = session
+            .csv_files
+            .first()
+            .and_then(|file| file.file_name())
+            .map(|name| name.to_string_lossy().into_owned());
+        if session.csv_files.is_empty() {
+            failures.push((
+                variant,
+                AppError::NoCsvFound {
+                    subject: subject.to_string(),
+                    directory: session.location.directory.clone(),
+                },
+            ));
+        } else if session.weekly_summary.is_none() {
+            failures.push((
+                variant,
+                AppError::InsufficientData {
+                    subject: subject.to_string(),
+                },
+            ));
+        } else {
+            sessions.push(session);
+        }
+    }
+
+    // The subject fails only when no variant could be summarized.
+    if sessions.is_empty() {
+        if let Some((_, error)) = failures.into_iter().next() {
+            return Err(error);
+        }
+        return Ok(sessions);
+    }
+    for (variant, error) in &failures {
+        match variant {
+            Some(variant) => eprintln!(
+                "Warning: skipping part5 variant {} of subject {}: {}",
+                variant, subject, error
+            ),
+            None => eprintln!(
+                "Warning: skipping a part5 variant of subject {}: {}",
+                subject, error
+            ),
+        }
+    }
+    Ok(sessions)
+}
+
+fn read_subject_list(file: &Path) -> AppResult<Vec<String>> {
+    let contents = fs::read_to_string(file).map_err(|err| {
+        AppError::Io(format!(
+            "Failed to read subject list {}: {}",
+            file.display(),
+            err
+        ))
+    })?;
+
+    let mut subjects: Vec<String> = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let first_field = trimmed
+            .split([',', ';', '\t'])
+            .next()
+            .unwrap_or("")
+            .trim()
+            .trim_matches('"');
+
+        // Tolerate a header row such as "subject" at the top of a CSV export.
+        if index == 0 && !first_field.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+
+        if !first_field.is_empty() && !subjects.iter().any(|s| s == first_field) {
+            subjects.push(first_field.to_string());
+        }
+    }
+
+    Ok(subjects)
+}
+
+/// The most subjects one `--range` may expand to.
+const MAX_RANGE_SUBJECTS: u64 = 10_000;
+
+fn expand_subject_range(rules: &SubjectRules, range: &str) -> AppResult<Vec<String>> {
+    let (start, end) = range.split_once('-').ok_or_else(|| {
+        format!(
+            "Invalid range '{}'. Expected START-END, e.g. 7001-7099.",
+            range
+        )
+    })?;
+
+    let start = start.trim();
+    let end = end.trim();
+    for bound in [start, end] {
+        rules
+            .validate(bound)
+            .map_err(|reason| AppError::InvalidSubject {
+                subject: bound.to_string(),
+                reason,
+            })?;
+    }
+
+    // Both bounds are digits by now, but a long `subject_digits` can still overflow.
+    let parse = |bound: &str| {
+        bound
+            .parse::<u64>()
+            .map_err(|err| AppError::InvalidSubject {
+                subject: bound.to_string(),
+                reason: format!("cannot be used in a range: {}", err),
+            })
+    };
+    let start = parse(start)?;
+    let end = parse(end)?;
+    if start > end {
+        return Err(format!("Invalid range '{}': start is greater than end.", range).into());
+    }
+    if end - start >= MAX_RANGE_SUBJECTS {
+        return Err(format!(
+            "Invalid range '{}': it covers more than {} subjects.",
+            range, MAX_RANGE_SUBJECTS
+        )
+        .into());
+    }
+
+    Ok((start..=end)
+        .map(|number| format!("{:0width$}", number, width = rules.digits))
+        .collect())
+}
+
+fn cohort_headers() -> Vec<String> {
+    let mut headers = vec![
+        "subject".to_string(),
+        "status".to_string(),
+        "dataset".to_string(),
+        "ggir_version".to_string(),
+        "window".to_string(),
+        "light_threshold_mg".to_string(),
+        "moderate_threshold_mg".to_string(),
+        "vigorous_threshold_mg".to_string(),
+        "sleep_thresholds".to_string(),
+        "files".to_string(),
+        "day_rows".to_string(),
+    ];
+    for label in SUMMARY_LABELS {
+        headers.push(format!("weekly_{}_hours", label.to_lowercase()));
+    }
+    headers.push("weekly_mvpa_minutes".to_string());
+    for label in SUMMARY_LABELS {
+        headers.push(format!("daily_{}_hours", label.to_lowercase()));
+    }
+    headers.push("daily_mvpa_minutes".to_string());
+    headers.push("daily_sedentary_hours".to_string());
+    headers
+}
+
+/// One row per session; a session without a weekly summary gets its status and blank cells.
+fn cohort_rows(sessions: &[Session]) -> Vec<Vec<String>> {
+    let width = cohort_headers().len();
+    sessions
+        .iter()
+        .map(|session| {
+            let status = match (&session.weekly_summary, session.csv_files.is_empty()) {
+                (Some(_), _) => "ok",
+                (None, true) => "no data",
+                (None, false) => "insufficient data",
+            };
+            let mut row = vec![
+                session.subject_number.clone(),
+                status.to_string(),
+                session.location.dataset.clone(),
+                session.location.ggir_version.clone().unwrap_or_default(),
+            ];
+            match &session.part5 {
+                Some(params) => row.extend([
+                    params.window.code().to_string(),
+                    params.light_threshold.to_string(),
+                    params.moderate_threshold.to_string(),
+                    params.vigorous_threshold.to_string(),
+                    format!("T{}A{}", params.time_threshold, params.angle_threshold),
+                ]),
+                None => row.extend(std::iter::repeat(String::new()).take(5)),
+            }
+            row.push(session.csv_files.len().to_string());
+            row.push(session.total_rows().to_string());
+            let Some(summary) = &session.weekly_summary else {
+                row.resize(width, String::new());
+                return row;
+            };
+            row.extend(summary.average_hours.iter().map(|v| format!("{:.2}", v)));
+            row.push(format!("{:.2}", summary.weekly_mvpa_minutes));
+            row.extend(
+                summary
+                    .daily_average_hours
+                    .iter()
+                    .map(|v| format!("{:.2}", v)),
+            );
+            row.push(format!("{:.2}", summary.daily_mvpa_minutes));
+            row.push(format!("{:.2}", summary.daily_sedentary_hours));
+            row
+        })
+        .collect()
+}
+
+/// The row for a skipped batch subject: the reason as its status and blank cells.
+fn failure_row(failure: &BatchFailure) -> Vec<String> {
+    let mut row = vec![
+        failure.subject.clone(),
+        format!("failed: {}", failure.error),
+    ];
+    row.resize(cohort_headers().len(), String::new());
+    row
+}
+
+fn render_table(headers: &[String], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let format_line = |cells: &[String]| -> String {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        format!("{}\n", padded.join("  ").trim_end())
+    };
+
+    let mut output = format_line(headers);
+    let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    output.push_str(&format_line(&rule));
+    for row in rows {
+        output.push_str(&format_line(row));
+    }
+    output
+}
+
+fn render_csv(headers: &[String], rows: &[Vec<String>]) -> AppResult<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(headers)?;
+    for row in rows {
+        writer.write_record(row)?;
+    }
+    let bytes = writer
+        .into_inner()
+        .map_err(|err| AppError::Io(err.to_string()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn handle_explain(overrides: &ConfigOverrides, subject: &str) -> AppResult<()> {
+    let settings = resolve_settings(overrides)?;
+    settings
+        .subject_rules
+        .validate(subject)
+        .map_err(|reason| AppError::InvalidSubject {
+            subject: subject.to_string(),
+            reason,
+        })?;
+    settings.check_dataset_scope(Some(&[subject.to_string()]))?;
+    println!(
+        "Share path: {} (from {})",
+        settings.share_path.display(),
+        settings.share_source
+    );
+    println!("Layout: {}", settings.layout);
+
+    for root in &settings.fallback_roots {
+        println!("Fallback root: {}", root.display());
+    }
+
+    let location = build_subject_directory(&settings, subject)?;
+    println!("Share root: {}", location.share_root.display());
+    println!("Study: {}", location.study);
+    let dataset_source = if settings.lookup == LookupMode::Scan {
+        "found by scanning the share"
+    } else if let Some(dataset) = settings.dataset_for(&location.study) {
+        if is_wildcard(dataset) {
+            "newest matching --dataset that holds the subject"
+        } else {
+            "--dataset flag"
+        }
+    } else if is_wildcard(&settings.subject_rules.resolve(subject)?.dataset) {
+        "newest that holds the subject"
+    } else {
+        "configured"
+    };
+    println!("Dataset: {} ({})", location.dataset, dataset_source);
+    if !location.other_datasets.is_empty() {
+        println!(
+            "Other datasets with this subject: {}",
+            location.other_datasets.join(", ")
+        );
+    }
+    if !location.newer_datasets.is_empty() {
+        println!(
+            "Newer datasets without this subject: {}",
+            location.newer_datasets.join(", ")
+        );
+    }
+    match (&location.ggir_version, &settings.ggir_version) {
+        (Some(version), Some(_)) => println!("GGIR version: {} (pinned)", version),
+        (Some(version), None) => println!("GGIR version: {} (newest found)", version),
+        (None, _) => println!("GGIR version: not used by the layout"),
+    }
+    if !location.other_ggir_versions.is_empty() {
+        println!(
+            "Other GGIR versions with this subject: {}",
+            location.other_ggir_versions.join(", ")
+        );
+    }
+
+    let subject_directory = location.directory;
+    let exists = subject_directory.exists();
+    println!(
+        "Subject directory: {} ({})",
+        subject_directory.display(),
+        if exists { "exists" } else { "missing" }
+    );
+    if !exists {
+        return Ok(());
+    }
+
+    let variants = discover_part5_variants(&subject_directory, &settings.part5_pattern);
+    println!(
+        "Part5 pattern: {} ({} variant(s) matched)",
+        settings.part5_pattern,
+        variants.len()
+    );
+    for variant in &variants {
+        println!("  {} ({} file(s))", variant.name, variant.files.len());
+        println!("    {}", variant.describe());
+        for file in &variant.files {
+            println!("    {}", file.display());
+        }
+    }
+    if variants.len() > 1 && !settings.all_variants {
+        println!("Several variants match; reports need --variant or --all-variants.");
+    }
+
+    let aliases = settings.column_aliases(location.ggir_version.as_deref());
+    for file in variants.iter().flat_map(|variant| &variant.files) {
+        println!();
+        println!("{}", file.display());
+        explain_file(file, &aliases)?;
+    }
+
+    Ok(())
+}
+
+/// Prints the column mapping for one CSV and how many rows the pipeline would keep.
+fn explain_file(file: &Path, aliases: &ColumnAliases) -> AppResult<()> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_path(file)
+        .map_err(|err| AppError::Io(format!("Failed to open {}: {}", file.display(), err)))?;
+    let headers = reader.headers()?.clone();
+
+    let column_lookup = match locate_required_columns(&headers, aliases) {
+        Ok(lookup) => lookup,
+        Err(missing) => {
+            println!("  Missing required column(s): {}", missing.join(", "));
+            return Ok(());
+        }
+    };
+
+    println!("  Column mapping:");
+    for line in column_lookup.describe(&headers) {
+        println!("    {}", line);
+    }
+
+    let mut kept = 0usize;
+    let mut skipped = 0usize;
+    for result in reader.records() {
+        let outcome = result
+            .map_err(|err| err.to_string())
+            .and_then(|record| extract_metrics_from_record(file, &record, &column_lookup));
+        match outcome {
+            Ok(_) => kept += 1,
+            Err(_) => skipped += 1,
+        }
+    }
+    println!("  Rows: {} kept, {} skipped", kept, skipped);
+
+    Ok(())
+}
+
+fn handle_completions(shell: Shell) -> AppResult<()> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut io::stdout());
+    Ok(())
+}
+
+fn handle_manpage() -> AppResult<()> {
+    let man = clap_mangen::Man::new(Cli::command());
+    man.render(&mut io::stdout())?;
+    Ok(())
+}
+
+fn handle_tui(overrides: &ConfigOverrides) -> AppResult<()> {
+    let settings = resolve_settings(overrides)?;
+    settings.check_dataset_scope(None)?;
+    tui::run(settings)
+}
+
+/// Finds a subject's folder and the part5 variants in it that match the configured pattern.
+fn find_subject(
+    settings: &Settings,
+    subject_number: &str,
+) -> AppResult<(SubjectLocation, Vec<Part5Variant>)> {
+    find_variants(settings, build_subject_directory(settings, subject_number)?)
+}
+
+/// Checks that a resolved subject folder exists and lists the part5 variants in it.
+fn find_variants(
+    settings: &Settings,
+    location: SubjectLocation,
+) -> AppResult<(SubjectLocation, Vec<Part5Variant>)> {
+    if !location.directory.exists() {
+        return Err(AppError::SubjectDirectoryMissing(location.directory));
+    }
+
+    let variants = discover_part5_variants(&location.directory, &settings.part5_pattern);
+    Ok((location, variants))
+}
+
+/// Reads each selected part5 variant of a subject in a resolved folder, keeping a failure to
+/// read one variant separate from the others.
+fn build_variant_sessions(
+    settings: &Settings,
+    subject_number: &str,
+    location: SubjectLocation,
+) -> AppResult<Vec<AppResult<Session>>> {
+    let (location, variants) = find_variants(settings, location)?;
+
+    if variants.len() > 1 && !settings.all_variants {
+        return Err(AppError::AmbiguousVariant {
+            subject: subject_number.to_string(),
+            variants: variants.into_iter().map(|variant| variant.name).collect(),
+        });
+    }
+    if variants.is_empty() {
+        return Ok(vec![read_session(settings, subject_number, location, None)]);
+    }
+
+    Ok(variants
+        .into_iter()
+        .map(|variant| read_session(settings, subject_number, location.clone(), Some(variant)))
+        .collect())
+}
+
+/// Reads one part5 variant into a session; without a variant the session has no files.
+fn read_session(
+    settings: &Settings,
+    subject_number: &str,
+    location: SubjectLocation,
+    variant: Option<Part5Variant>,
+) -> AppResult<Session> {
+    let mut warnings = Vec::new();
+    if location.share_root != settings.share_path {
+        warnings.push(format!(
+            "Warning: subject {} was not found on {}; reading it from {}.",
+            subject_number,
+            settings.share_path.display(),
+            location.share_root.display()
+        ));
+    }
+    if !location.other_datasets.is_empty() {
+        warnings.push(format!(
+            "Warning: subject {} is also in dataset {}; using {}.",
+            subject_number,
+            location.other_datasets.join(", "),
+            location.dataset
+        ));
+    }
+    if !location.newer_datasets.is_empty() {
+        warnings.push(format!(
+            "Warning: subject {} is not in the newer dataset {}; using {}.",
+            subject_number,
+            location.newer_datasets.join(", "),
+            location.dataset
+        ));
+    }
+    if let Some(version) = &location.ggir_version {
+        if !location.other_ggir_versions.is_empty() {
+            warnings.push(format!(
+                "Warning: subject {} also has output from GGIR {}; using GGIR {}.",
+                subject_number,
+                location.other_ggir_versions.join(", "),
+                version
+            ));
+        }
+    }
+
+    if let Some(variant) = variant.as_ref().filter(|variant| variant.params.is_none()) {
+        warnings.push(format!(
+            "Warning: cannot read the analysis parameters from file name {}; its thresholds are unknown.",
+            variant.name
+        ));
+    }
+    let (part5, csv_files) = match variant {
+        Some(variant) => (variant.params, variant.files),
+        None => (None, Vec::new()),
+    };
+    let aliases = settings.column_aliases(location.ggir_version.as_deref());
+    let activity_data =
+        collect_activity_metrics(&csv_files, &aliases, settings.verbose, &mut warnings)?;
+    let weekly_summary = compute_weekly_summary(&activity_data);
+
+    Ok(Session {
+        share_path: location.share_root.clone(),
+        subject_number: subject_number.to_string(),
+        location,
+        part5,
+        csv_files,
+        warnings,
+        activity_data,
+        weekly_summary,
+    })
+}
+
+impl Session {
+    fn print_warnings(&self) {
+        for warning in &self.warnings {
+            eprintln!("{}", warning);
+        }
+    }
+
+    fn total_rows(&self) -> usize {
+        self.activity_data
+            .values()
+            .map(|records| records.len())
+            .sum()
+    }
+}
+
+const SUMMARY_LABELS: [&str; 5] = ["Sleep", "IN", "LIG", "MOD", "VIG"];
+
+fn print_weekly_summary(summary: Option<&WeeklySummary>) {
+    for line in weekly_summary_lines(summary) {
+        println!("{}", line);
+    }
+}
+
+fn weekly_summary_lines(summary: Option<&WeeklySummary>) -> Vec<String> {
+    let Some(summary) = summary else {
+        return vec![
+            "Unable to compute weekly or daily averages due to insufficient overlapping data."
+                .to_string(),
+        ];
+    };
+
+    let mut lines = vec!["weekly_average (hours per 7-day week):".to_string()];
+    for (label, value) in SUMMARY_LABELS.iter().zip(summary.average_hours.iter()) {
+        lines.push(format!("  {:<5}: {:.2}", label, value));
+    }
+    lines.push(format!(
+        "weekly_mvpa (minutes per 7-day week): {:.2}",
+        summary.weekly_mvpa_minutes
+    ));
+    lines.push("daily_average (hours per day):".to_string());
+    for (label, value) in SUMMARY_LABELS
+        .iter()
+        .zip(summary.daily_average_hours.iter())
+    {
+        lines.push(format!("  {:<5}: {:.2}", label, value));
+    }
+    lines.push(format!(
+        "daily_mvpa (minutes per day): {:.2}",
+        summary.daily_mvpa_minutes
+    ));
+    lines.push(format!(
+        "daily_sedentary (hours per day, excluding sleep): {:.2}",
+        summary.daily_sedentary_hours
+    ));
+    if !summary.average_sleep_by_weekday.is_empty() {
+        lines.push("average_sleep_by_weekday (hours):".to_string());
+        for (weekday, hours) in &summary.average_sleep_by_weekday {
+            lines.push(format!(
+                "  {:<9}: {:.2}",
+                weekday_display_name(*weekday),
+                hours
+            ));
+        }
+    }
+    lines
+}
+
+fn session_to_json(session: &Session) -> Value {
+    json!({
+        "subject": session.subject_number,
+        "share_root": session.share_path.display().to_string(),
+        "subject_directory": session.location.directory.display().to_string(),
+        "study": session.location.study,
+        "dataset": session.location.dataset,
+        "ggir_version": session.location.ggir_version,
+        "part5": session.part5.as_ref().map(Part5Params::to_json),
+        "files": session
+            .csv_files
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>(),
+        "participants": session.activity_data.len(),
+        "day_rows": session.total_rows(),
+        "summary": session.weekly_summary.as_ref().map(weekly_summary_to_json),
+    })
+}
+
+fn weekly_summary_to_json(summary: &WeeklySummary) -> Value {
+    let hours_by_label = |values: &[f64; 5]| -> serde_json::Map<String, Value> {
+        SUMMARY_LABELS
+            .iter()
+            .zip(values.iter())
+            .map(|(label, value)| (label.to_lowercase(), json!(value)))
+            .collect()
+    };
+
+    let sleep_by_weekday: serde_json::Map<String, Value> = summary
+        .average_sleep_by_weekday
+        .iter()
+        .map(|(weekday, hours)| (weekday_display_name(*weekday).to_string(), json!(hours)))
+        .collect();
+
+    json!({
+        "weekly_average_hours": hours_by_label(&summary.average_hours),
+        "weekly_mvpa_minutes": summary.weekly_mvpa_minutes,
+        "daily_average_hours": hours_by_label(&summary.daily_average_hours),
+        "daily_mvpa_minutes": summary.daily_mvpa_minutes,
+        "daily_sedentary_hours": summary.daily_sedentary_hours,
+        "average_sleep_by_weekday": sleep_by_weekday,
+    })
+}
+
+fn config_file_path(overrides: &ConfigOverrides) -> AppResult<PathBuf> {
+    match &overrides.config_file {
+        Some(path) => Ok(path.clone()),
+        None => Ok(determine_config_dir()?.join("config.toml")),
+    }
+}
+
+/// The system-wide config file, deployed by IT for every user of the machine.
+fn system_config_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        std::env::var_os("ProgramData").map(|dir| {
+            PathBuf::from(dir)
+                .join("report-builder")
+                .join("config.toml")
+        })
+    } else {
+        Some(PathBuf::from("/etc/report-builder/config.toml"))
+    }
+}
+
+/// The environment variable that sets a config key, e.g. `REPORT_BUILDER_LAYOUT`.
+fn config_env_var(key: &str) -> String {
+    format!("REPORT_BUILDER_{}", key.to_uppercase())
+}
+
+/// The config after merging every layer, and where each value in it came from.
+struct LayeredConfig {
+    config: Config,
+    // The layer that set each key, e.g. "config file /etc/report-builder/config.toml".
+    origins: BTreeMap<String, String>,
+    // The config files that were found and read, lowest precedence first.
+    files: Vec<PathBuf>,
+    // The profile laid over the files, if one was selected and applied.
+    profile: Option<String>,
+}
+
+impl LayeredConfig {
+    fn origin(&self, key: &str) -> String {
+        self.origins
+            .get(key)
+            .cloned()
+            .unwrap_or_else(|| "built-in default".to_string())
+    }
+}
+
+/// Merges, from lowest to highest precedence: the system config file, the per-user config file
+/// (or `--config`), `./report-builder.toml`, the selected profile from any of those files, and
+/// `REPORT_BUILDER_*` environment variables. Missing files are skipped.
+fn load_layered_config(
+    overrides: &ConfigOverrides,
+    profile: Option<&str>,
+) -> AppResult<LayeredConfig> {
+    let config_file = config_file_path(overrides)?;
+    let mut candidates = Vec::new();
+    if let Some(path) = system_config_path() {
+        candidates.push(("system config file", path));
+    }
+    candidates.push(("config file", config_file.clone()));
+    candidates.push((
+        "project config file",
+        std::env::current_dir()?.join(PROJECT_CONFIG_FILE),
+    ));
+
+    let mut layers = Vec::new();
+    for (label, path) in candidates {
+        if path.is_file() {
+            let config = load_config(&path)?;
+            layers.push((format!("{} {}", label, path.display()), path, config));
+        }
+    }
+
+    let mut config = Config::default();
+    let mut origins = BTreeMap::new();
+    for (origin, path, layer) in &layers {
+        // An older file is only upgraded in memory, so its `version` key is not its own.
+        let migrated_from = config_file_version(path).filter(|version| *version < CONFIG_VERSION);
+        if let Some(version) = migrated_from {
+            eprintln!(
+                "Note: {} is config version {}; run `report-builder --config {} config migrate` to upgrade it.",
+                path.display(),
+                version,
+                path.display()
+            );
+        }
+        for key in layer.set_keys() {
+            if key == "version" && migrated_from.is_some() {
+                continue;
+            }
+            origins.insert(key, origin.clone());
+        }
+        config = layer.clone().or(config);
+    }
+
+    // A profile only exported in the environment must not break runs whose config lacks it.
+    let defined = |name: &str| {
+        config
+            .profiles
+            .as_ref()
+            .is_some_and(|profiles| profiles.contains_key(name))
+    };
+    let profile = match profile {
+        Some(name) if overrides.profile_from_env && !defined(name) => {
+            if config.profiles.is_some() {
+                eprintln!(
+                    "Warning: profile '{}' from {} is not defined; using the settings without a profile.",
+                    name, PROFILE_ENV
+                );
+            }
+            None
+        }
+        profile => profile,
+    };
+    if let Some(name) = profile {
+        config = config
+            .with_profile(name)
+            .map_err(|message| AppError::ConfigInvalid {
+                path: config_file.clone(),
+                message,
+            })?;
+        for (origin, _, layer) in &layers {
+            let Some(layer_profile) = layer.profiles.as_ref().and_then(|p| p.get(name)) else {
+                continue;
+            };
+            for key in layer_profile.set_keys() {
+                origins.insert(key, format!("profile {} in {}", name, origin));
+            }
+        }
+    }
+
+    let environment = environment_config()?;
+    for key in environment.set_keys() {
+        origins.insert(
+            key.clone(),
+            format!("{} environment variable", config_env_var(&key)),
+        );
+    }
+    config = environment.or(config);
+
+    Ok(LayeredConfig {
+        config,
+        origins,
+        files: layers.into_iter().map(|(_, path, _)| path).collect(),
+        profile: profile.map(str::to_string),
+    })
+}
+
+/// Reads `REPORT_BUILDER_<KEY>` for every key `config set` accepts.
+fn environment_config() -> AppResult<Config> {
+    let source = PathBuf::from("REPORT_BUILDER_* environment variables");
+    let mut table = toml::Table::new();
+    for key in CONFIG_KEYS {
+        let var = config_env_var(key);
+        let value = std::env::var(&var).unwrap_or_default();
+        if value.trim().is_empty() {
+            continue;
+        }
+        let value = config_value(key, value.trim()).map_err(|err| AppError::ConfigInvalid {
+            path: source.clone(),
+            message: format!("{}: {}", var, err),
+        })?;
+        table.insert(key.to_string(), value);
+    }
+
+    let config: Config =
+        table
+            .try_into()
+            .map_err(|err: toml::de::Error| AppError::ConfigInvalid {
+                path: source.clone(),
+                message: err.to_string(),
+            })?;
+    validate_config(&config).map_err(|message| AppError::ConfigInvalid {
+        path: source,
+        message,
+    })?;
+    Ok(config)
+}
+
+/// Picks the share path from `--share-path`, then the merged config layers, and reads the
+/// remaining settings from those layers.
+fn resolve_settings(overrides: &ConfigOverrides) -> AppResult<Settings> {
+    let config_file = config_file_path(overrides)?;
+    let layered = load_layered_config(overrides, overrides.profile.as_deref())?;
+
+    let flag = overrides
+        .share_path
+        .as_deref()
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .map(|path| (path.to_string(), "--share-path flag".to_string()));
+    let config = layered.config.clone();
+
+    let mut roots: Vec<PathBuf> = config
+        .share_roots
+        .iter()
+        .flatten()
+        .map(|root| root.trim())
+        .filter(|root| !root.is_empty())
+        .map(native_path)
+        .collect();
+
+    let (share_path, share_source) = match flag {
+        Some((share, source)) => (native_path(&share), source),
+        None => match config
+            .share_path
+            .as_ref()
+            .and_then(SharePathSetting::for_this_os)
+        {
+            Some(share) => (native_path(share), layered.origin("share_path")),
+            None if !roots.is_empty() => (
+                roots.remove(0),
+                format!("share_roots in {}", layered.origin("share_roots")),
+            ),
+            None if layered.files.is_empty() => {
+                return Err(AppError::ConfigMissing(config_file));
+            }
+            None => {
+                let message = match &config.share_path {
+                    Some(SharePathSetting::PerOs(_)) => format!(
+                        "share_path has no entry for {} or default. Re-run `report-builder init`.",
+                        std::env::consts::OS
+                    ),
+                    _ => "share_path is not set. Re-run `report-builder init`.".to_string(),
+                };
+                return Err(AppError::ConfigInvalid {
+                    path: config_file.clone(),
+                    message,
+                });
+            }
+        },
+    };
+    // Files copied by `sync` mirror the share layout, so the cache is the last root to try.
+    // Without a data directory there is no cache, which must not stop the run.
+    if let Ok(synced) = synced_root() {
+        if synced.is_dir() {
+            roots.push(synced);
+        }
+    }
+    roots.retain(|root| *root != share_path);
+    roots.dedup();
+
+    let studies = config.studies.unwrap_or_else(default_studies);
+    let dataset = overrides
+        .dataset
+        .as_deref()
+        .map(|value| parse_dataset_override(value, &studies))
+        .transpose()?
+        .flatten();
+
+    Ok(Settings {
+        share_path,
+        share_source,
+        fallback_roots: roots,
+        layout: config.layout.unwrap_or_else(|| DEFAULT_LAYOUT.to_string()),
+        ggir_version: overrides
+            .ggir_version
+            .clone()
+            .or(config.ggir_version)
+            .map(|version| version.trim().to_string())
+            .filter(|version| !version.is_empty()),
+        dataset,
+        lookup: overrides.lookup.or(config.lookup).unwrap_or_default(),
+        subject_rules: SubjectRules {
+            digits: config.subject_digits.unwrap_or(DEFAULT_SUBJECT_DIGITS),
+            studies,
+        },
+        part5_pattern: overrides
+            .variant
+            .clone()
+            .or(config.part5_pattern)
+            .map(|pattern| pattern.trim().to_string())
+            .filter(|pattern| !pattern.is_empty())
+            .unwrap_or_else(|| DEFAULT_PART5_PATTERN.to_string()),
+        all_variants: overrides.all_variants,
+        column_aliases: config.column_aliases.unwrap_or_default(),
+        verbose: overrides.verbose,
+        scanned_index: Rc::new(OnceCell::new()),
+    })
+}
+
+/// Parses `--dataset [STUDY=]NAME`, checking that a named study is in the study table.
+fn parse_dataset_override(
+    value: &str,
+    studies: &[StudyRule],
+) -> AppResult<Option<DatasetOverride>> {
+    let (study, dataset) = match value.split_once('=') {
+        Some((study, dataset)) => (Some(study.trim()), dataset.trim()),
+        None => (None, value.trim()),
+    };
+    if dataset.is_empty() {
+        return Ok(None);
+    }
+    let study = match study {
+        Some(name) => match studies
+            .iter()
+            .find(|rule| rule.study.eq_ignore_ascii_case(name))
+        {
+            Some(rule) => Some(rule.study.clone()),
+            None => {
+                let known: Vec<&str> = studies.iter().map(|rule| rule.study.as_str()).collect();
+                return Err(format!(
+                    "--dataset names unknown study '{}'; expected {}.",
+                    name,
+                    join_alternatives(&known)
+                )
+                .into());
+            }
+        },
+        None => None,
+    };
+    Ok(Some(DatasetOverride {
+        study,
+        dataset: dataset.to_string(),
+    }))
+}
+
+fn handle_config(overrides: &ConfigOverrides, action: &ConfigAction) -> AppResult<()> {
+    let config_file = config_file_path(overrides)?;
+
+    match action {
+        ConfigAction::Path => {
+            println!("{}", config_file.display());
+        }
+        ConfigAction::Show { origin } => {
+            let layered = load_layered_config(overrides, overrides.profile.as_deref())?;
+            if layered.files.is_empty() {
+                return Err(AppError::ConfigMissing(config_file));
+            }
+            for file in &layered.files {
+                println!("# {}", file.display());
+            }
+            if let Some(name) = &layered.profile {
+                println!("# profile {}", name);
+            }
+
+            if !*origin {
+                print!("{}", serialize_config(&layered.config)?);
+                return Ok(());
+            }
+            let table = toml::Table::try_from(&layered.config)
+                .map_err(|err| AppError::Other(err.to_string()))?;
+            for (key, value) in &table {
+                if key == "profiles" {
+                    let names: Vec<&str> = value
+                        .as_table()
+                        .map(|profiles| profiles.keys().map(String::as_str).collect())
+                        .unwrap_or_default();
+                    println!("# profiles: {} (select with --profile)", names.join(", "));
+                    continue;
+                }
+                println!("{} = {}  # {}", key, value, layered.origin(key));
+            }
+        }
+        ConfigAction::Get { key } => {
+            ensure_known_config_key(key)?;
+            let layered = load_layered_config(overrides, overrides.profile.as_deref())?;
+            let table = toml::Table::try_from(&layered.config)
+                .map_err(|err| AppError::Other(err.to_string()))?;
+            match table.get(key.as_str()) {
+                Some(toml::Value::String(value)) => println!("{}", value),
+                Some(value) => println!("{}", value),
+                None => {
+                    return Err(AppError::ConfigInvalid {
+                        path: config_file,
+                        message: format!("{} is not set.", key),
+                    })
+                }
+            }
+        }
+        ConfigAction::Set { key, value } => {
+            ensure_known_config_key(key)?;
+            let mut table = if config_file.exists() {
+                read_config_table(&config_file)?
+            } else {
+                toml::Table::new()
+            };
+            let value = config_value(key, value)?;
+            let target = match overrides.profile.as_deref() {
+                Some(name) => table
+                    .entry("profiles")
+                    .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                    .as_table_mut()
+                    .and_then(|profiles| {
+                        profiles
+                            .entry(name)
+                            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                            .as_table_mut()
+                    })
+                    .ok_or_else(|| AppError::ConfigInvalid {
+                        path: config_file.clone(),
+                        message: format!("profiles.{} must be a table.", name),
+                    })?,
+                None => &mut table,
+            };
+            // As with `init`, a per-OS share_path table only has this platform's entry replaced.
+            match target.get_mut(key.as_str()) {
+                Some(toml::Value::Table(paths)) if key == "share_path" => {
+                    paths.insert(std::env::consts::OS.to_string(), value);
+                }
+                _ => {
+                    target.insert(key.clone(), value);
+                }
+            }
+
+            // Round-trip through Config so only a loadable file is ever written.
+            let config: Config =
+                table
+                    .try_into()
+                    .map_err(|err: toml::de::Error| AppError::ConfigInvalid {
+                        path: config_file.clone(),
+                        message: err.to_string(),
+                    })?;
+            validate_config(&config).map_err(|message| AppError::ConfigInvalid {
+                path: config_file.clone(),
+                message,
+            })?;
+            save_config(&config_file, &config)?;
+            match overrides.profile.as_deref() {
+                Some(name) => println!(
+                    "Set {} for profile {} in {}",
+                    key,
+                    name,
+                    config_file.display()
+                ),
+                None => println!("Set {} in {}", key, config_file.display()),
+            }
+        }
+        ConfigAction::Migrate => {
+            let config = load_config(&config_file)?;
+            match config_file_version(&config_file) {
+                Some(version) if version >= CONFIG_VERSION => println!(
+                    "{} is already config version {}.",
+                    config_file.display(),
+                    version
+                ),
+                _ => save_config(&config_file, &config)?,
+            }
+        }
+        ConfigAction::Validate => {
+            let layered = load_layered_config(overrides, overrides.profile.as_deref())?;
+            if layered.files.is_empty() {
+                return Err(AppError::ConfigMissing(config_file));
+            }
+            for file in &layered.files {
+                println!("{} parses correctly.", file.display());
+            }
+
+            let settings = resolve_settings(overrides)?;
+            // Any reachable root is enough; the others are reported but not fatal.
+            let mut first_failure = None;
+            let mut reachable = 0;
+            for root in settings.share_roots() {
+                match check_share_layout(root, &settings.layout) {
+                    Ok(()) => {
+                        reachable += 1;
+                        println!("{} is reachable.", root.display());
+                    }
+                    Err(reason) => {
+                        println!("{} is not usable: {}", root.display(), reason);
+                        first_failure.get_or_insert(AppError::ShareUnreachable {
+                            path: root.to_path_buf(),
+                            reason,
+                        });
+                    }
+                }
+            }
+            if reachable == 0 {
+                if let Some(error) = first_failure {
+                    return Err(error);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts a command-line value into the TOML type the key expects.
+fn config_value(key: &str, raw: &str) -> AppResult<toml::Value> {
+    match key {
+        "share_roots" => Ok(toml::Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|root| !root.is_empty())
+                .map(|root| toml::Value::String(root.to_string()))
+                .collect(),
+        )),
+        "subject_digits" => raw
+            .trim()
+            .parse::<i64>()
+            .map(toml::Value::Integer)
+            .map_err(|_| format!("{} must be a whole number.", key).into()),
+        _ => Ok(toml::Value::String(raw.to_string())),
+    }
+}
+
+fn ensure_known_config_key(key: &str) -> AppResult<()> {
+    if CONFIG_KEYS.contains(&key) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unknown config key '{}'. Known keys: {}",
+            key,
+            CONFIG_KEYS.join(", ")
+        )
+        .into())
+    }
+}
+
+fn read_config_table(config_file: &Path) -> AppResult<toml::Table> {
+    if !config_file.exists() {
+        return Err(AppError::ConfigMissing(config_file.to_path_buf()));
+    }
+
+    let contents = fs::read_to_string(config_file)?;
+    let mut table = contents
+        .parse::<toml::Table>()
+        .map_err(|err| AppError::ConfigInvalid {
+            path: config_file.to_path_buf(),
+            message: err.to_string(),
+        })?;
+
+    // Older files are upgraded in memory only; `config migrate`, `init` and `config set` write
+    // the upgrade out.
+    let version = config_table_version(config_file, &table)?;
+    match version.cmp(&CONFIG_VERSION) {
+        Ordering::Greater => eprintln!(
+            "Warning: {} is config version {}, newer than the {} this build reads; keys it does not know are ignored.",
+            config_file.display(),
+            version,
+            CONFIG_VERSION
+        ),
+        Ordering::Less => migrate_config(&mut table, version),
+        Ordering::Equal => {}
+    }
+
+    Ok(table)
+}
+
+/// The format version a parsed config file declares; files without one are version 1.
+fn config_table_version(config_file: &Path, table: &toml::Table) -> AppResult<u32> {
+    match table.get("version") {
+        None => Ok(1),
+        Some(toml::Value::Integer(version)) => u32::try_from(*version)
+            .ok()
+            .filter(|version| *version >= 1)
+            .ok_or_else(|| AppError::ConfigInvalid {
+                path: config_file.to_path_buf(),
+                message: format!("version {} is not a valid config version.", version),
+            }),
+        Some(_) => Err(AppError::ConfigInvalid {
+            path: config_file.to_path_buf(),
+            message: "version must be a whole number.".to_string(),
+        }),
+    }
+}
+
+/// The version of the config file on disk, or `None` when it is missing or unreadable.
+fn config_file_version(config_file: &Path) -> Option<u32> {
+    let table = fs::read_to_string(config_file)
+        .ok()?
+        .parse::<toml::Table>()
+        .ok()?;
+    config_table_version(config_file, &table).ok()
+}
+
+/// Rewrites a table read from an older config file into the current format, one version at a
+/// time, and stamps it with `CONFIG_VERSION`.
+fn migrate_config(table: &mut toml::Table, from: u32) {
+    for version in from..CONFIG_VERSION {
+        match version {
+            // Version 1 files predate the version key; their keys carry over unchanged.
+            1 => {}
+            _ => unreachable!("no migration from config version {}", version),
+        }
+    }
+    table.insert(
+        "version".to_string(),
+        toml::Value::Integer(CONFIG_VERSION.into()),
+    );
+}
+
+fn serialize_config(config: &Config) -> AppResult<String> {
+    toml::to_string(config).map_err(|err| AppError::Other(err.to_string()))
+}
+
+/// Writes the config in the current format. An older file being replaced is first copied to
+/// `<file>.v<version>.bak`, since rewriting it drops its comments.
+fn save_config(config_file: &Path, config: &Config) -> AppResult<()> {
+    if let Some(parent) = config_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if let Some(version) =
+        config_file_version(config_file).filter(|version| *version < CONFIG_VERSION)
+    {
+        let mut backup = config_file.as_os_str().to_owned();
+        backup.push(format!(".v{}.bak", version));
+        let backup = PathBuf::from(backup);
+        fs::copy(config_file, &backup)?;
+        eprintln!(
+            "Upgraded {} from config version {} to {}; the previous file is saved as {}.",
+            config_file.display(),
+            version,
+            CONFIG_VERSION,
+            backup.display()
+        );
+    }
+    let config = Config {
+        version: Some(CONFIG_VERSION),
+        ..config.clone()
+    };
+    fs::write(config_file, serialize_config(&config)?)?;
+    Ok(())
+}
+
+/// Reads, upgrades in memory and validates one config file, warning about keys this build
+/// ignores.
+fn load_config(config_file: &Path) -> AppResult<Config> {
+    let table = read_config_table(config_file)?;
+    let config: Config =
+        table
+            .try_into()
+            .map_err(|err: toml::de::Error| AppError::ConfigInvalid {
+                path: config_file.to_path_buf(),
+                message: err.to_string(),
+            })?;
+
+    for key in config.unknown_keys() {
+        eprintln!(
+            "Warning: unknown key '{}' in {} is ignored.",
+            key,
+            config_file.display()
+        );
+    }
+
+    validate_config(&config).map_err(|message| AppError::ConfigInvalid {
+        path: config_file.to_path_buf(),
+        message,
+    })?;
+
+    Ok(config)
+}
+
+/// Checks the values serde cannot, such as the placeholders used in `layout`. Each profile is
+/// checked as it resolves, laid over the top-level keys.
+fn validate_config(config: &Config) -> Result<(), String> {
+    if let Some(profiles) = &config.profiles {
+        for (name, profile) in profiles {
+            if profile.profiles.is_some() {
+                return Err(format!("Profile '{}' cannot define profiles.", name));
+            }
+            if profile.version.is_some() {
+                return Err(format!(
+                    "Profile '{}' cannot set version; it belongs at the top of the file.",
+                    name
+                ));
+            }
+            let resolved = config.clone().with_profile(name)?;
+            validate_config(&resolved)
+                .map_err(|message| format!("Profile '{}': {}", name, message))?;
+        }
+    }
+
+    if let Some(SharePathSetting::PerOs(paths)) = &config.share_path {
+        if let Some(unknown) = paths
+            .keys()
+            .find(|key| !SHARE_PATH_PLATFORMS.contains(&key.as_str()))
+        {
+            return Err(format!(
+                "Unknown share_path platform '{}'. Use {}.",
+                unknown,
+                join_alternatives(&SHARE_PATH_PLATFORMS)
+            ));
+        }
+    }
+
+    if let Some(layout) = &config.layout {
+        validate_layout(layout)?;
+    }
+
+    for (column, aliases) in config.column_aliases.iter().flatten() {
+        if !REQUIRED_COLUMNS.contains(&column.as_str()) {
+            return Err(format!(
+                "column_aliases has unknown column '{}'. Use {}.",
+                column,
+                join_alternatives(&REQUIRED_COLUMNS)
+            ));
+        }
+        if aliases.iter().any(|alias| alias.trim().is_empty()) {
+            return Err(format!("column_aliases.{} has an empty alias.", column));
+        }
+    }
+
+    if let Some(pattern) = &config.part5_pattern {
+        if pattern.contains(['/', '\\']) {
+            return Err(format!(
+                "part5_pattern '{}' must be a file name, not a path.",
+                pattern
+            ));
+        }
+    }
+
+    let digits = config.subject_digits.unwrap_or(DEFAULT_SUBJECT_DIGITS);
+    if digits == 0 {
+        return Err("subject_digits must be at least 1.".to_string());
+    }
+
+    if let Some(studies) = &config.studies {
+        if studies.is_empty() {
+            return Err("studies must list at least one study.".to_string());
+        }
+        let mut seen: Vec<&str> = Vec::new();
+        for rule in studies {
+            if rule.prefixes.is_empty() {
+                return Err(format!("Study {} has no prefixes.", rule.study));
+            }
+            for prefix in &rule.prefixes {
+                if prefix.is_empty()
+                    || prefix.len() > digits
+                    || !prefix.chars().all(|c| c.is_ascii_digit())
+                {
+                    return Err(format!(
+                        "Prefix '{}' for study {} must be 1 to {} digits.",
+                        prefix, rule.study, digits
+                    ));
+                }
+                if seen.contains(&prefix.as_str()) {
+                    return Err(format!(
+                        "Prefix '{}' is listed for more than one study.",
+                        prefix
+                    ));
+                }
+                seen.push(prefix);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Asks until a valid subject number is entered; `None` once input ends.
+fn prompt_for_subject_number(rules: &SubjectRules) -> Result<Option<String>, io::Error> {
+    loop {
+        println!("Enter the subject number ({}):", rules.describe());
+        print!("> ");
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Ok(None);
+        }
+        let trimmed = input.trim();
+
+        if trimmed.is_empty() {
+            println!("A subject number is required. Please try again.");
+            continue;
+        }
+
+        match rules.validate(trimmed) {
+            Ok(()) => return Ok(Some(trimmed.to_string())),
+            Err(reason) => println!("{} Please try again.", reason),
+        }
+    }
+}
+
+impl Settings {
+    /// Every share root in priority order, starting with `share_path`.
+    fn share_roots(&self) -> Vec<&Path> {
+        std::iter::once(self.share_path.as_path())
+            .chain(self.fallback_roots.iter().map(PathBuf::as_path))
+            .collect()
+    }
+
+    /// The same settings reading from another share root.
+    fn with_share_root(&self, root: &Path) -> Settings {
+        Settings {
+            share_path: root.to_path_buf(),
+            fallback_roots: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Header names accepted for each required column besides its own: the configured aliases,
+    /// then the built-in ones for `ggir_version` (all of them when the version is unknown).
+    fn column_aliases(&self, ggir_version: Option<&str>) -> ColumnAliases {
+        REQUIRED_COLUMNS
+            .iter()
+            .map(|&column| {
+                let mut names = self.column_aliases.get(column).cloned().unwrap_or_default();
+                names.extend(
+                    BUILTIN_COLUMN_ALIASES
+                        .iter()
+                        .filter(|builtin| builtin.column == column)
+                        .filter(|builtin| {
+                            ggir_version.map_or(true, |version| {
+                                compare_versions(version, builtin.before) == Ordering::Less
+                            })
+                        })
+                        .map(|builtin| builtin.alias.to_string()),
+                );
+                (column, names)
+            })
+            .collect()
+    }
+
+    /// The line shown when a command starts, naming the share and any fallback roots.
+    fn share_summary(&self) -> String {
+        let mut summary = format!(
+            "Using share path: {} (from {})",
+            self.share_path.display(),
+            self.share_source
+        );
+        if !self.fallback_roots.is_empty() {
+            summary.push_str(&format!(
+                "; falling back to {}",
+                self.fallback_roots
+                    .iter()
+                    .map(|root| root.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        summary
+    }
+
+    /// The `--dataset` folder or pattern to use for `study`, if the flag applies to it.
+    fn dataset_for(&self, study: &str) -> Option<&str> {
+        let dataset = self.dataset.as_ref()?;
+        match &dataset.study {
+            Some(scoped) if scoped != study => None,
+            _ => Some(&dataset.dataset),
+        }
+    }
+
+    /// Rejects a `--dataset` without `STUDY=` when the subjects (`None` for every subject) may
+    /// span several studies, since one dataset name cannot belong to all of them.
+    fn check_dataset_scope(&self, subjects: Option<&[String]>) -> AppResult<()> {
+        let Some(DatasetOverride {
+            study: None,
+            dataset,
+        }) = &self.dataset
+        else {
+            return Ok(());
+        };
+        // Scanning can find a subject under any study, so every study is in play.
+        let mut studies: Vec<&str> = match subjects {
+            Some(subjects) if self.lookup == LookupMode::Prefix => subjects
+                .iter()
+                .filter_map(|subject| self.subject_rules.study_for(subject))
+                .map(|rule| rule.study.as_str())
+                .collect(),
+            _ => self
+                .subject_rules
+                .studies
+                .iter()
+                .map(|rule| rule.study.as_str())
+                .collect(),
+        };
+        studies.sort_unstable();
+        studies.dedup();
+        if studies.len() > 1 {
+            return Err(format!(
+                "--dataset {} would apply to {}; name the study with --dataset STUDY={}.",
+                dataset,
+                studies.join(", "),
+                dataset
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl SubjectRules {
+    fn prefixes(&self) -> Vec<&str> {
+        self.studies
+            .iter()
+            .flat_map(|rule| rule.prefixes.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// Human-readable form of the rules, e.g. "4 digits starting with 7, 8, or 9".
+    fn describe(&self) -> String {
+        format!(
+            "{} digits starting with {}",
+            self.digits,
+            join_alternatives(&self.prefixes())
+        )
+    }
+
+    fn validate(&self, value: &str) -> Result<(), String> {
+        if value.len() != self.digits || !value.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!(
+                "Subject numbers must be a {}-digit integer.",
+                self.digits
+            ));
+        }
+
+        if self.study_for(value).is_none() {
+            return Err(format!(
+                "Subject numbers must start with {}.",
+                join_alternatives(&self.prefixes())
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Finds the study whose prefix matches; the longest matching prefix wins.
+    fn study_for(&self, subject_number: &str) -> Option<&StudyRule> {
+        self.studies
+            .iter()
+            .flat_map(|rule| rule.prefixes.iter().map(move |prefix| (prefix, rule)))
+            .filter(|(prefix, _)| subject_number.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, rule)| rule)
+    }
+
+    fn resolve(&self, subject_number: &str) -> AppResult<&StudyRule> {
+        self.study_for(subject_number)
+            .ok_or_else(|| AppError::InvalidSubject {
+                subject: subject_number.to_string(),
+                reason: format!(
+                    "Subject numbers must start with {}.",
+                    join_alternatives(&self.prefixes())
+                ),
+            })
+    }
+}
+
+/// Joins values as "7", "7 or 8", or "7, 8, or 9".
+fn join_alternatives(values: &[&str]) -> String {
+    match values {
+        [] => String::new(),
+        [only] => only.to_string(),
+        [first, second] => format!("{} or {}", first, second),
+        [rest @ .., last] => format!("{}, or {}", rest.join(", "), last),
+    }
+}
+
+fn build_subject_directory(
+    settings: &Settings,
+    subject_number: &str,
+) -> AppResult<SubjectLocation> {
+    if settings.lookup == LookupMode::Prefix {
+        // The first root that holds the subject serves it; otherwise report the primary share.
+        let primary = locate_by_prefix(settings, subject_number);
+        if primary
+            .as_ref()
+            .is_ok_and(|location| location.directory.is_dir())
+        {
+            return primary;
+        }
+        for root in &settings.fallback_roots {
+            if let Ok(location) = locate_by_prefix(&settings.with_share_root(root), subject_number)
+            {
+                if location.directory.is_dir() {
+                    return Ok(location);
+                }
+            }
+        }
+        return primary;
+    }
+
+    let mut matches = scan_for_subject(settings, subject_number)?;
+    if let Some(dataset) = &settings.dataset {
+        matches.retain(|found| dataset.selects(found));
+    }
+    match matches.len() {
+        // Nothing found: fall back to the prefix rules so the error names the expected folder.
+        0 => locate_by_prefix(settings, subject_number),
+        1 => Ok(locate_match(settings, subject_number, &matches[0])),
+        _ => Err(AppError::AmbiguousSubject {
+            subject: subject_number.to_string(),
+            matches,
+        }),
+    }
+}
+
+/// Resolves the subject folder under `settings.share_path` from the study prefix table.
+fn locate_by_prefix(settings: &Settings, subject_number: &str) -> AppResult<SubjectLocation> {
+    let rule = settings.subject_rules.resolve(subject_number)?;
+    let segments = layout_segments(&settings.layout);
+
+    let datasets = candidate_datasets(settings, &segments, rule);
+    if datasets.is_empty() {
+        // Only a pattern can match nothing; naming it in a path would point nowhere.
+        let pattern = settings.dataset_for(&rule.study).unwrap_or(&rule.dataset);
+        return match dataset_parent(&settings.share_path, &segments, &rule.study) {
+            Some(parent) => Err(AppError::Other(format!(
+                "No dataset matching {} under {}.",
+                pattern,
+                parent.display()
+            ))),
+            // Without a dataset folder in the layout the pattern never reaches the path.
+            None => Ok(locate_in_dataset(
+                settings,
+                &segments,
+                &rule.study,
+                pattern,
+                subject_number,
+            )),
+        };
+    }
+
+    // Prefer the newest dataset that holds the subject; otherwise report the newest one.
+    let mut found: Vec<SubjectLocation> = Vec::new();
+    let mut newer = Vec::new();
+    for dataset in datasets {
+        let location =
+            locate_in_dataset(settings, &segments, &rule.study, &dataset, subject_number);
+        if location.directory.is_dir() {
+            found.push(location);
+        } else if found.is_empty() {
+            newer.push(dataset);
+        }
+    }
+
+    if found.is_empty() {
+        // Nothing holds the subject, so `newer` lists every dataset, the newest first.
+        return Ok(locate_in_dataset(
+            settings,
+            &segments,
+            &rule.study,
+            &newer[0],
+            subject_number,
+        ));
+    }
+
+    let mut location = found.remove(0);
+    location.other_datasets = found.into_iter().map(|other| other.dataset).collect();
+    location.newer_datasets = newer;
+    Ok(location)
+}
+
+/// The datasets to try for a study, newest first: the `--dataset` flag for that study or else
+/// the configured dataset, either as one folder or as every folder matching its pattern.
+fn candidate_datasets(settings: &Settings, segments: &[&str], rule: &StudyRule) -> Vec<String> {
+    let dataset = settings.dataset_for(&rule.study).unwrap_or(&rule.dataset);
+    if is_wildcard(dataset) {
+        detect_datasets(&settings.share_path, segments, &rule.study, dataset)
+    } else {
+        vec![dataset.to_string()]
+    }
+}
+
+/// Resolves the subject folder within one dataset, picking the GGIR version as configured.
+fn locate_in_dataset(
+    settings: &Settings,
+    segments: &[&str],
+    study: &str,
+    dataset: &str,
+    subject_number: &str,
+) -> SubjectLocation {
+    let values = [
+        ("study", study),
+        ("dataset", dataset),
+        ("subject", subject_number),
+    ];
+
+    let render = |version: Option<&str>| {
+        let mut values = values.to_vec();
+        values.push(("ggir_version", version.unwrap_or(DEFAULT_GGIR_VERSION)));
+        render_layout(&settings.share_path, segments, &values)
+    };
+
+    let (ggir_version, other_ggir_versions) = if !settings.layout.contains("{ggir_version}") {
+        (None, Vec::new())
+    } else if let Some(pinned) = &settings.ggir_version {
+        (Some(pinned.clone()), Vec::new())
+    } else {
+        // Prefer versions that actually contain this subject, newest first.
+        let available = detect_ggir_versions(&settings.share_path, segments, &values);
+        let mut with_subject: Vec<String> = available
+            .iter()
+            .filter(|version| render(Some(version.as_str())).is_dir())
+            .cloned()
+            .collect();
+        if with_subject.is_empty() {
+            (available.into_iter().next(), Vec::new())
+        } else {
+            let newest = with_subject.remove(0);
+            (Some(newest), with_subject)
+        }
+    };
+
+    SubjectLocation {
+        share_root: settings.share_path.clone(),
+        directory: render(ggir_version.as_deref()),
+        study: study.to_string(),
+        dataset: dataset.to_string(),
+        other_datasets: Vec::new(),
+        newer_datasets: Vec::new(),
+        ggir_version,
+        other_ggir_versions,
+    }
+}
+
+/// The folder of a study that holds its dataset folders, if the layout has a `{dataset}`.
+fn dataset_parent(share_path: &Path, segments: &[&str], study: &str) -> Option<PathBuf> {
+    let index = segments
+        .iter()
+        .position(|segment| segment.contains("{dataset}"))?;
+    Some(render_layout(
+        share_path,
+        &segments[..index],
+        &[("study", study)],
+    ))
+}
+
+/// Lists the dataset folders of a study that match a dataset pattern, newest first.
+fn detect_datasets(
+    share_path: &Path,
+    segments: &[&str],
+    study: &str,
+    dataset_pattern: &str,
+) -> Vec<String> {
+    let Some(index) = segments
+        .iter()
+        .position(|segment| segment.contains("{dataset}"))
+    else {
+        return Vec::new();
+    };
+
+    let values = [("study", study)];
+    let Some(parent) = dataset_parent(share_path, segments, study) else {
+        return Vec::new();
+    };
+    let pattern = fill_placeholders(segments[index], &values);
+    let Ok(entries) = fs::read_dir(&parent) else {
+        return Vec::new();
+    };
+
+    let mut datasets: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            match_placeholder(&pattern, "{dataset}", &entry.file_name().to_string_lossy())
+        })
+        .filter(|dataset| wildcard_match(dataset_pattern, dataset))
+        .collect();
+    datasets.sort_by(|a, b| compare_versions(b, a));
+    datasets
+}
+
+/// Lists the GGIR versions present in the folder that holds `GGIR-*` outputs, newest first.
+fn detect_ggir_versions(
+    share_path: &Path,
+    segments: &[&str],
+    values: &[(&str, &str)],
+) -> Vec<String> {
+    let Some(index) = segments
+        .iter()
+        .position(|segment| segment.contains("{ggir_version}"))
+    else {
+        return Vec::new();
+    };
+
+    let parent = render_layout(share_path, &segments[..index], values);
+    let pattern = fill_placeholders(segments[index], values);
+    let Ok(entries) = fs::read_dir(&parent) else {
+        return Vec::new();
+    };
+
+    let mut versions: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            match_placeholder(
+                &pattern,
+                "{ggir_version}",
+                &entry.file_name().to_string_lossy(),
+            )
+        })
+        .collect();
+    versions.sort_by(|a, b| compare_versions(b, a));
+    versions
+}
+
+/// Orders dotted version strings numerically, so 3.10.0 sorts after 3.2.6.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .split(['.', '-'])
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    parts(a).cmp(&parts(b)).then_with(|| a.cmp(b))
+}
+
+/// Lists every subject folder the layout can produce, across all known studies and GGIR versions,
+/// with the share root, study and dataset it was found in.
+fn list_subjects(settings: &Settings) -> Vec<(String, SubjectMatch, PathBuf)> {
+    let segments = layout_segments(&settings.layout);
+    let Some(subject_index) = segments
+        .iter()
+        .position(|segment| segment.contains("{subject}"))
+    else {
+        return Vec::new();
+    };
+
+    let mut subjects: Vec<(String, SubjectMatch, PathBuf)> = Vec::new();
+    for root in settings.share_roots() {
+        let settings = settings.with_share_root(root);
+        for rule in &settings.subject_rules.studies {
+            for dataset in candidate_datasets(&settings, &segments, rule) {
+                // Roots and datasets are visited in priority order, so the first hit wins.
+                for (number, path) in
+                    subject_folders(&settings, &segments, subject_index, &rule.study, &dataset)
+                {
+                    if !subjects.iter().any(|(known, _, _)| *known == number) {
+                        let found = SubjectMatch {
+                            share_root: root.to_path_buf(),
+                            study: rule.study.clone(),
+                            dataset: dataset.clone(),
+                        };
+                        subjects.push((number, found, path));
+                    }
+                }
+            }
+        }
+    }
+
+    subjects.sort_by(|a, b| a.0.cmp(&b.0));
+    subjects
+}
+
+/// Lists the subject folders in one study dataset, taking each from its newest GGIR version.
+fn subject_folders(
+    settings: &Settings,
+    segments: &[&str],
+    subject_index: usize,
+    study: &str,
+    dataset: &str,
+) -> Vec<(String, PathBuf)> {
+    let base_values = [("study", study), ("dataset", dataset)];
+
+    let mut subjects: Vec<(String, PathBuf)> = Vec::new();
+    for version in ggir_versions_in(settings, segments, &base_values) {
+        let mut values = base_values.to_vec();
+        values.push(("ggir_version", version.as_str()));
+        // Versions are visited newest first, so the first hit wins.
+        for (number, path) in subject_folders_at(settings, segments, subject_index, &values) {
+            if !subjects.iter().any(|(known, _)| *known == number) {
+                subjects.push((number, path));
+            }
+        }
+    }
+    subjects
+}
+
+/// The GGIR versions to look in for one study dataset: the pinned one, or every one found.
+fn ggir_versions_in(
+    settings: &Settings,
+    segments: &[&str],
+    base_values: &[(&str, &str)],
+) -> Vec<String> {
+    if let Some(pinned) = &settings.ggir_version {
+        return vec![pinned.clone()];
+    }
+    let detected = detect_ggir_versions(&settings.share_path, segments, base_values);
+    if detected.is_empty() {
+        vec![DEFAULT_GGIR_VERSION.to_string()]
+    } else {
+        detected
+    }
+}
 
-    let result = match cli.command {
-        Some(Commands::Init) => handle_init(),
-        None => run_interactive(),
+/// Lists the subject folders for one fully resolved study, dataset and GGIR version.
+fn subject_folders_at(
+    settings: &Settings,
+    segments: &[&str],
+    subject_index: usize,
+    values: &[(&str, &str)],
+) -> Vec<(String, PathBuf)> {
+    let parent = render_layout(&settings.share_path, &segments[..subject_index], values);
+    let pattern = fill_placeholders(segments[subject_index], values);
+    let Ok(entries) = fs::read_dir(&parent) else {
+        return Vec::new();
     };
 
-    if let Err(err) = result {
-        eprintln!("Error: {}", err);
-        std::process::exit(1);
-    }
+    let mut subjects: Vec<(String, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            let number =
+                match_placeholder(&pattern, "{subject}", &entry.file_name().to_string_lossy())?;
+            let mut subject_values = values.to_vec();
+            subject_values.push(("subject", number.as_str()));
+            let path = render_layout(&settings.share_path, segments, &subject_values);
+            Some((number, path))
+        })
+        .collect();
+    subjects.sort();
+    subjects
 }
 
-fn handle_init() -> Result<(), Box<dyn std::error::Error>> {
-    let share_path = prompt_for_share_path()?;
-    let config_dir = determine_config_dir()?;
-    fs::create_dir_all(&config_dir)?;
-
-    let config_file = config_dir.join("config.toml");
-    let contents = format!("share_path = \"{}\"\n", share_path);
-    fs::write(&config_file, contents)?;
+/// A study dataset that holds a subject's folder, as found by scanning the share roots.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+struct SubjectMatch {
+    share_root: PathBuf,
+    study: String,
+    dataset: String,
+}
 
-    println!("Saved vosslabhpc share path to {}", config_file.display());
+impl std::fmt::Display for SubjectMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.study, self.dataset)
+    }
+}
 
-    Ok(())
+/// Every subject folder found in the configured study datasets, cached between runs.
+#[derive(Debug, Deserialize, Serialize)]
+struct SubjectIndex {
+    // See `subject_index_fingerprint`.
+    fingerprint: String,
+    subjects: BTreeMap<String, Vec<SubjectMatch>>,
 }
 
-fn prompt_for_share_path() -> Result<String, io::Error> {
-    let example_path = example_share_path();
+fn locate_match(
+    settings: &Settings,
+    subject_number: &str,
+    found: &SubjectMatch,
+) -> SubjectLocation {
+    let segments = layout_segments(&settings.layout);
+    locate_in_dataset(
+        &settings.with_share_root(&found.share_root),
+        &segments,
+        &found.study,
+        &found.dataset,
+        subject_number,
+    )
+}
 
-    loop {
-        println!(
-            "Enter the path to the vosslabhpc share (e.g., {}):",
-            example_path
-        );
-        print!("> ");
-        io::stdout().flush()?;
+/// Looks a subject up in the cached index, rescanning the share when the subject is missing
+/// from the cache or a cached folder has disappeared. An index scanned during this run is
+/// trusted as is, so a batch of absent subjects costs one scan.
+fn scan_for_subject(settings: &Settings, subject_number: &str) -> AppResult<Vec<SubjectMatch>> {
+    let fingerprint = subject_index_fingerprint(settings);
+    if let Some(index) = settings
+        .scanned_index
+        .get()
+        .filter(|index| index.fingerprint == fingerprint)
+    {
+        return Ok(index
+            .subjects
+            .get(subject_number)
+            .cloned()
+            .unwrap_or_default());
+    }
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        let trimmed = input.trim();
+    let index_file = determine_cache_dir()?.join("subject-index.json");
 
-        if trimmed.is_empty() {
-            println!("A value is required. Please try again.");
-            continue;
+    if let Some(index) = load_subject_index(&index_file, settings) {
+        if let Some(matches) = index.subjects.get(subject_number) {
+            if matches.iter().all(|found| {
+                locate_match(settings, subject_number, found)
+                    .directory
+                    .is_dir()
+            }) {
+                return Ok(matches.clone());
+            }
         }
+    }
 
-        return Ok(trimmed.to_string());
+    eprintln!("Scanning the share for subject folders...");
+    let index = build_subject_index(settings);
+    if let Err(err) = save_subject_index(&index_file, &index) {
+        eprintln!("Warning: could not cache the subject index: {}", err);
     }
+    let matches = index
+        .subjects
+        .get(subject_number)
+        .cloned()
+        .unwrap_or_default();
+    // Another copy of the settings may have scanned with a different configuration; keep
+    // whichever index came first.
+    let _ = settings.scanned_index.set(index);
+    Ok(matches)
 }
 
-fn determine_config_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let project =
-        ProjectDirs::from("com", "hbc", "report-builder")
-            .ok_or("Unable to determine the user's configuration directory.")?;
-    Ok(project.config_dir().to_path_buf())
+/// Hashes every setting `build_subject_index` reads, so an index built under another
+/// configuration is never reused.
+fn subject_index_fingerprint(settings: &Settings) -> String {
+    let inputs = json!({
+        "share_roots": settings.share_roots(),
+        "layout": settings.layout,
+        "studies": settings.subject_rules.studies,
+        "ggir_version": settings.ggir_version,
+    });
+    format!("{:x}", Sha256::digest(inputs.to_string()))
 }
 
-fn example_share_path() -> &'static str {
-    if cfg!(target_os = "macos") {
-        "/Volumes/vosslabhpc"
-    } else if cfg!(target_os = "windows") {
-        r"\\vosslabhpc"
-    } else {
-        "/mnt/vosslabhpc"
-    }
+/// Reads the cached index if it was built from the same settings.
+fn load_subject_index(index_file: &Path, settings: &Settings) -> Option<SubjectIndex> {
+    let contents = fs::read_to_string(index_file).ok()?;
+    let index: SubjectIndex = serde_json::from_str(&contents).ok()?;
+    (index.fingerprint == subject_index_fingerprint(settings)).then_some(index)
 }
 
-fn run_interactive() -> Result<(), Box<dyn std::error::Error>> {
-    let config = load_config()?;
-    let share_path = Path::new(&config.share_path).to_path_buf();
-
-    println!("Using configured share path: {}", share_path.display());
+fn save_subject_index(index_file: &Path, index: &SubjectIndex) -> AppResult<()> {
+    if let Some(parent) = index_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(index_file, serde_json::to_string_pretty(index)?)?;
+    Ok(())
+}
 
-    let subject_number = prompt_for_subject_number()?;
-    let subject_directory = build_subject_directory(&share_path, &subject_number)?;
+/// Scans every dataset of every configured study on each share root, whatever the subject
+/// prefixes say.
+fn build_subject_index(settings: &Settings) -> SubjectIndex {
+    let segments = layout_segments(&settings.layout);
+    let mut subjects: BTreeMap<String, Vec<SubjectMatch>> = BTreeMap::new();
 
-    if !subject_directory.exists() {
-        return Err(format!(
-            "Subject directory does not exist: {}",
-            subject_directory.display()
-        )
-        .into());
+    if let Some(subject_index) = segments
+        .iter()
+        .position(|segment| segment.contains("{subject}"))
+    {
+        for root in settings.share_roots() {
+            for rule in &settings.subject_rules.studies {
+                let datasets = if is_wildcard(&rule.dataset) {
+                    detect_datasets(root, &segments, &rule.study, &rule.dataset)
+                } else {
+                    vec![rule.dataset.clone()]
+                };
+                for dataset in datasets {
+                    let root_settings = settings.with_share_root(root);
+                    for (number, _) in subject_folders(
+                        &root_settings,
+                        &segments,
+                        subject_index,
+                        &rule.study,
+                        &dataset,
+                    ) {
+                        // A mirror holding the same dataset is a copy, not another location.
+                        let matches = subjects.entry(number).or_default();
+                        if !matches
+                            .iter()
+                            .any(|found| found.study == rule.study && found.dataset == dataset)
+                        {
+                            matches.push(SubjectMatch {
+                                share_root: root.to_path_buf(),
+                                study: rule.study.clone(),
+                                dataset: dataset.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
     }
 
-    let csv_files = discover_target_csv(&subject_directory)?;
+    SubjectIndex {
+        fingerprint: subject_index_fingerprint(settings),
+        subjects,
+    }
+}
 
-    println!(
-        "Located {} target file(s) for subject {} under {}",
-        csv_files.len(),
-        subject_number,
-        subject_directory.display()
-    );
+/// Splits a layout template into path segments, accepting either separator.
+fn layout_segments(template: &str) -> Vec<&str> {
+    template.split(['/', '\\']).collect()
+}
 
-    if csv_files.is_empty() {
-        println!("No matching files found; verify the subject data is available.");
-        return Ok(());
+/// Checks that a layout only uses known placeholders and names the subject folder.
+fn validate_layout(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed placeholder in layout '{}'.", template))?;
+        let name = &rest[start + 1..start + end];
+        if !LAYOUT_PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "Unknown placeholder {{{}}} in layout. Known placeholders: {}",
+                name,
+                LAYOUT_PLACEHOLDERS
+                    .iter()
+                    .map(|placeholder| format!("{{{}}}", placeholder))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        rest = &rest[start + end + 1..];
     }
 
-    for path in &csv_files {
-        println!("  {}", path.display());
+    if !template.contains("{subject}") {
+        return Err("The layout must contain a {subject} placeholder.".to_string());
     }
 
-    let activity_data = collect_activity_metrics(&csv_files)?;
+    Ok(())
+}
 
-    println!(
-        "Prepared metrics for {} participant(s).",
-        activity_data.len()
-    );
+/// Replaces every `{name}` in a single segment with its value.
+fn fill_placeholders(segment: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(segment.to_string(), |filled, (name, value)| {
+            filled.replace(&format!("{{{}}}", name), value)
+        })
+}
 
-    for (id, records) in activity_data.iter().take(5) {
-        println!("  {} -> {} day(s) of data", id, records.len());
+/// Builds a path from layout segments; a `{share}` segment expands to the whole share path.
+fn render_layout(share_path: &Path, segments: &[&str], values: &[(&str, &str)]) -> PathBuf {
+    let mut path = PathBuf::new();
+    for (index, segment) in segments.iter().enumerate() {
+        if segment.is_empty() {
+            if index == 0 {
+                path.push(std::path::MAIN_SEPARATOR_STR);
+            }
+            continue;
+        }
+        if *segment == "{share}" {
+            path.push(share_path);
+            continue;
+        }
+        path.push(fill_placeholders(segment, values));
     }
-    if activity_data.len() > 5 {
-        println!("  ...");
+    path
+}
+
+/// Extracts the value a single placeholder took in `name`, e.g. `7012` from `sub-7012`.
+fn match_placeholder(pattern: &str, placeholder: &str, name: &str) -> Option<String> {
+    let (prefix, suffix) = pattern.split_once(placeholder)?;
+    let value = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
     }
+}
 
-    let weekly_summary = compute_weekly_summary(&activity_data);
+/// How GGIR cut the recording into days for a part5 summary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum WindowType {
+    MidnightToMidnight,
+    WakingToWaking,
+    OnsetToOnset,
+}
 
-    if let Some(ref summary) = weekly_summary {
-        println!("weekly_average (hours per 7-day week):");
-        const LABELS: [&str; 5] = ["Sleep", "IN", "LIG", "MOD", "VIG"];
-        for (label, value) in LABELS.iter().zip(summary.average_hours.iter()) {
-            println!("  {:<5}: {:.2}", label, value);
-        }
-        println!(
-            "weekly_mvpa (minutes per 7-day week): {:.2}",
-            summary.weekly_mvpa_minutes
-        );
-        println!("daily_average (hours per day):");
-        for (label, value) in LABELS.iter().zip(summary.daily_average_hours.iter()) {
-            println!("  {:<5}: {:.2}", label, value);
+impl WindowType {
+    fn from_code(code: &str) -> Option<Self> {
+        match code.to_ascii_uppercase().as_str() {
+            "MM" => Some(WindowType::MidnightToMidnight),
+            "WW" => Some(WindowType::WakingToWaking),
+            "OO" => Some(WindowType::OnsetToOnset),
+            _ => None,
         }
-        println!(
-            "daily_mvpa (minutes per day): {:.2}",
-            summary.daily_mvpa_minutes
-        );
-        println!(
-            "daily_sedentary (hours per day, excluding sleep): {:.2}",
-            summary.daily_sedentary_hours
-        );
-        if !summary.average_sleep_by_weekday.is_empty() {
-            println!("average_sleep_by_weekday (hours):");
-            for (weekday, hours) in &summary.average_sleep_by_weekday {
-                println!("  {:<9}: {:.2}", weekday_display_name(*weekday), hours);
-            }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            WindowType::MidnightToMidnight => "MM",
+            WindowType::WakingToWaking => "WW",
+            WindowType::OnsetToOnset => "OO",
         }
-    } else {
-        println!(
-            "Unable to compute weekly or daily averages due to insufficient overlapping data."
-        );
     }
 
-    let session = Session {
-        share_path,
-        subject_number,
-        subject_directory,
-        activity_data,
-        weekly_summary,
-    };
+    fn description(self) -> &'static str {
+        match self {
+            WindowType::MidnightToMidnight => "midnight to midnight",
+            WindowType::WakingToWaking => "waking to waking",
+            WindowType::OnsetToOnset => "sleep onset to sleep onset",
+        }
+    }
+}
 
-    let total_rows: usize = session
-        .activity_data
-        .values()
-        .map(|records| records.len())
-        .sum();
+/// The analysis parameters GGIR encodes in a part5 day summary name, e.g.
+/// `part5_daysummary_MM_L44.8M100.6V428.8_T5A5.csv`.
+#[derive(Debug, Clone, PartialEq)]
+struct Part5Params {
+    window: WindowType,
+    // Acceleration cutpoints (mg) for light, moderate and vigorous activity.
+    light_threshold: f64,
+    moderate_threshold: f64,
+    vigorous_threshold: f64,
+    // Sleep detection settings: time threshold (minutes) and angle threshold (degrees).
+    time_threshold: f64,
+    angle_threshold: f64,
+}
 
-    println!(
-        "Session ready with {} total day-level rows for downstream aggregation.",
-        total_rows
-    );
+impl Part5Params {
+    /// Parses a part5 day summary file name; any other file name yields `None`.
+    fn parse(file_name: &str) -> Option<Self> {
+        const PREFIX: &str = "part5_daysummary_";
+        const EXTENSION: &str = ".csv";
 
-    Ok(())
-}
+        let lower = file_name.to_ascii_lowercase();
+        if !lower.starts_with(PREFIX) || !lower.ends_with(EXTENSION) {
+            return None;
+        }
+        let stem = &file_name[PREFIX.len()..file_name.len() - EXTENSION.len()];
 
-fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
-    let config_file = determine_config_dir()?.join("config.toml");
+        let mut parts = stem.split('_');
+        let window = WindowType::from_code(parts.next()?)?;
+        let [light, moderate, vigorous] = parse_tagged_numbers(parts.next()?, ['L', 'M', 'V'])?;
+        let [time, angle] = parse_tagged_numbers(parts.next()?, ['T', 'A'])?;
+        if parts.next().is_some() {
+            return None;
+        }
 
-    if !config_file.exists() {
-        return Err("No configuration found. Please run `report-builder init` first.".into());
+        Some(Part5Params {
+            window,
+            light_threshold: light,
+            moderate_threshold: moderate,
+            vigorous_threshold: vigorous,
+            time_threshold: time,
+            angle_threshold: angle,
+        })
     }
 
-    let contents = fs::read_to_string(&config_file)?;
-    let config: Config = toml::from_str(&contents)?;
-
-    if config.share_path.trim().is_empty() {
-        return Err(format!(
-            "share_path in {} is empty. Re-run `report-builder init`.",
-            config_file.display()
+    /// Spells out the parameters for people reading a report.
+    fn describe(&self) -> String {
+        format!(
+            "{} window ({}), cutpoints light {} / moderate {} / vigorous {} mg, sleep T{} A{}",
+            self.window.code(),
+            self.window.description(),
+            self.light_threshold,
+            self.moderate_threshold,
+            self.vigorous_threshold,
+            self.time_threshold,
+            self.angle_threshold
         )
-        .into());
     }
 
-    Ok(config)
+    fn to_json(&self) -> Value {
+        json!({
+            "window": self.window.code(),
+            "light_threshold_mg": self.light_threshold,
+            "moderate_threshold_mg": self.moderate_threshold,
+            "vigorous_threshold_mg": self.vigorous_threshold,
+            "time_threshold_min": self.time_threshold,
+            "angle_threshold_deg": self.angle_threshold,
+        })
+    }
 }
 
-fn prompt_for_subject_number() -> Result<String, io::Error> {
-    loop {
-        println!("Enter the subject number (four digits starting with 7, 8, or 9):");
-        print!("> ");
-        io::stdout().flush()?;
+/// Reads numbers that each follow one tag letter, e.g. `L44.8M100.6V428.8` for tags L, M, V.
+fn parse_tagged_numbers<const N: usize>(text: &str, tags: [char; N]) -> Option<[f64; N]> {
+    let mut values = [0.0; N];
+    let mut rest = text;
+    for (index, tag) in tags.iter().enumerate() {
+        rest = rest
+            .strip_prefix(*tag)
+            .or_else(|| rest.strip_prefix(tag.to_ascii_lowercase()))?;
+        let end = match tags.get(index + 1) {
+            Some(next) => rest.find([*next, next.to_ascii_lowercase()])?,
+            None => rest.len(),
+        };
+        values[index] = rest[..end].parse().ok()?;
+        rest = &rest[end..];
+    }
+    Some(values)
+}
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        let trimmed = input.trim();
+/// One set of part5 parameters, with every file found for it under a subject folder.
+#[derive(Debug, Clone)]
+struct Part5Variant {
+    // File name of the first copy found, used when listing variants.
+    name: String,
+    // `None` when the file name does not encode the analysis parameters.
+    params: Option<Part5Params>,
+    files: Vec<PathBuf>,
+}
 
-        if trimmed.is_empty() {
-            println!("A subject number is required. Please try again.");
-            continue;
+impl Part5Variant {
+    fn describe(&self) -> String {
+        match &self.params {
+            Some(params) => params.describe(),
+            None => format!("{} (parameters not in file name)", self.name),
         }
+    }
+}
 
-        if trimmed.len() != 4 || !trimmed.chars().all(|c| c.is_ascii_digit()) {
-            println!("Subject numbers must be a four-digit integer. Please try again.");
+/// Groups the part5 day summaries under `subject_directory` whose name matches `pattern` by the
+/// parameters encoded in the name. A matching file whose name does not encode parameters is
+/// still a variant of its own, grouped with copies of the same name.
+fn discover_part5_variants(subject_directory: &Path, pattern: &str) -> Vec<Part5Variant> {
+    let mut variants: Vec<Part5Variant> = Vec::new();
+
+    for entry in WalkDir::new(subject_directory)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+    {
+        let Some(name) = entry.file_name().to_str() else {
+            continue;
+        };
+        if !wildcard_match(pattern, name) {
             continue;
         }
+        let params = Part5Params::parse(name);
+        let same_variant = |variant: &&mut Part5Variant| match &params {
+            Some(_) => variant.params == params,
+            None => variant.params.is_none() && variant.name == name,
+        };
 
-        match trimmed.chars().next() {
-            Some('7') | Some('8') | Some('9') => return Ok(trimmed.to_string()),
-            _ => {
-                println!("Subject numbers must start with 7, 8, or 9. Please try again.");
-            }
+        let name = name.to_string();
+        match variants.iter_mut().find(same_variant) {
+            Some(variant) => variant.files.push(entry.into_path()),
+            None => variants.push(Part5Variant {
+                name,
+                params,
+                files: vec![entry.into_path()],
+            }),
         }
     }
-}
-
-fn build_subject_directory(
-    base_share_path: &Path,
-    subject_number: &str,
-) -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let first_digit = subject_number
-        .chars()
-        .next()
-        .ok_or("Subject number cannot be empty.")?;
-
-    let (study, dataset) = match first_digit {
-        '7' => ("ObservationalStudy", "act-obs-final-test-2"),
-        '8' | '9' => ("InterventionStudy", "act-int-final-test-2"),
-        _ => return Err(
-            "Subject numbers must start with 7, 8, or 9. Validation should have prevented this."
-                .into(),
-        ),
-    };
-
-    let subject_folder = format!("sub-{}", subject_number);
 
-    let path = base_share_path
-        .join("Projects")
-        .join("BOOST")
-        .join(study)
-        .join("3-experiment")
-        .join("data")
-        .join(dataset)
-        .join("derivatives")
-        .join("GGIR-3.2.6")
-        .join(&subject_folder)
-        .join("accel");
+    // Unparsed variants go last.
+    variants.sort_by_key(|variant| {
+        (
+            variant.params.is_none(),
+            variant.params.as_ref().map(|params| params.window),
+            variant.name.clone(),
+        )
+    });
+    for variant in &mut variants {
+        variant.files.sort();
+    }
+    variants
+}
 
-    Ok(path)
+/// Whether a dataset or file name pattern uses `*` or `?` and so may match several names.
+fn is_wildcard(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
 }
 
-fn discover_target_csv(
-    subject_directory: &Path,
-) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
-    const TARGET_FILENAME: &str = "part5_daysummary_MM_L44.8M100.6V428.8_T5A5.csv";
-    let mut matches = Vec::new();
+/// Case-insensitive file name match where `*` is any run of characters and `?` is one.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
 
-    for entry in WalkDir::new(subject_directory)
-        .follow_links(false)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| e.file_type().is_file())
-    {
-        if entry
-            .path()
-            .file_name()
-            .and_then(|name| name.to_str())
-            .map(|name| name.eq_ignore_ascii_case(TARGET_FILENAME))
-            .unwrap_or(false)
-        {
-            matches.push(entry.into_path());
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` and the name index it currently stands in for.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
         }
     }
 
-    Ok(matches)
+    pattern[p..].iter().all(|&c| c == '*')
 }
 
 fn collect_activity_metrics(
     files: &[PathBuf],
-) -> Result<HashMap<String, Vec<DayMetrics>>, Box<dyn std::error::Error>> {
+    aliases: &ColumnAliases,
+    verbose: bool,
+    warnings: &mut Vec<String>,
+) -> AppResult<HashMap<String, Vec<DayMetrics>>> {
     let mut matrix: HashMap<String, Vec<DayMetrics>> = HashMap::new();
 
     for file in files {
@@ -364,37 +4352,51 @@ fn collect_activity_metrics(
             .has_headers(true)
             .trim(csv::Trim::All)
             .from_path(file)
-            .map_err(|err| format!("Failed to open {}: {}", file.display(), err))?;
+            .map_err(|err| AppError::Io(format!("Failed to open {}: {}", file.display(), err)))?;
 
         let headers = reader
             .headers()
-            .map_err(|err| format!("Failed to read headers from {}: {}", file.display(), err))?
+            .map_err(|err| {
+                AppError::Io(format!(
+                    "Failed to read headers from {}: {}",
+                    file.display(),
+                    err
+                ))
+            })?
             .clone();
 
-        let column_lookup = locate_required_columns(&headers).map_err(|missing| {
-            format!(
-                "File {} is missing required column(s): {}",
-                file.display(),
-                missing.join(", ")
-            )
+        let column_lookup = locate_required_columns(&headers, aliases).map_err(|missing| {
+            AppError::MissingColumns {
+                file: file.clone(),
+                columns: missing,
+            }
         })?;
+        if verbose {
+            eprintln!("Column mapping for {}:", file.display());
+            for line in column_lookup.describe(&headers) {
+                eprintln!("  {}", line);
+            }
+        }
 
         for result in reader.records() {
             let record = match result {
                 Ok(record) => record,
                 Err(err) => {
-                    eprintln!(
+                    warnings.push(format!(
                         "Skipping row in {} due to read error: {}",
                         file.display(),
                         err
-                    );
+                    ));
                     continue;
                 }
             };
 
-            if let Some(metrics) = extract_metrics_from_record(file, &record, &column_lookup) {
-                let id_key = metrics.id.clone();
-                matrix.entry(id_key).or_default().push(metrics);
+            match extract_metrics_from_record(file, &record, &column_lookup) {
+                Ok(metrics) => {
+                    let id_key = metrics.id.clone();
+                    matrix.entry(id_key).or_default().push(metrics);
+                }
+                Err(warning) => warnings.push(warning),
             }
         }
     }
@@ -517,8 +4519,8 @@ fn compute_weekly_summary(data: &HashMap<String, Vec<DayMetrics>>) -> Option<Wee
     })
 }
 
-fn sort_metrics_by_date(records: &mut Vec<DayMetrics>) {
-    records.sort_by(|a, b| compare_metrics(a, b));
+fn sort_metrics_by_date(records: &mut [DayMetrics]) {
+    records.sort_by(compare_metrics);
 }
 
 fn compare_metrics(a: &DayMetrics, b: &DayMetrics) -> Ordering {
@@ -576,6 +4578,36 @@ fn weekday_display_name(weekday: Weekday) -> &'static str {
     }
 }
 
+/// The part5 columns every day summary needs, named as current GGIR releases write them.
+const REQUIRED_COLUMNS: [&str; 8] = [
+    "ID",
+    "calendar_date",
+    "weekday",
+    "dur_spt_min",
+    "dur_day_total_IN_min",
+    "dur_day_total_LIG_min",
+    "dur_day_total_MOD_min",
+    "dur_day_total_VIG_min",
+];
+
+/// A header name older GGIR releases wrote for a required column.
+struct BuiltinAlias {
+    column: &'static str,
+    alias: &'static str,
+    // The first GGIR version that writes `column` instead.
+    before: &'static str,
+}
+
+/// GGIR 2.0 renamed the sleep period time columns from "night" to "spt".
+const BUILTIN_COLUMN_ALIASES: &[BuiltinAlias] = &[BuiltinAlias {
+    column: "dur_spt_min",
+    alias: "dur_night_min",
+    before: "2.0",
+}];
+
+/// Header names to try for each required column after its own name, in order.
+type ColumnAliases = BTreeMap<&'static str, Vec<String>>;
+
 struct ColumnLookup {
     id: usize,
     calendar_date: usize,
@@ -584,20 +4616,60 @@ struct ColumnLookup {
     sleep_minutes: usize,
 }
 
-fn locate_required_columns(headers: &StringRecord) -> Result<ColumnLookup, Vec<String>> {
+impl ColumnLookup {
+    /// Required column names paired with the header index each one resolved to.
+    fn mapping(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            ("ID", self.id),
+            ("calendar_date", self.calendar_date),
+            ("weekday", self.weekday),
+            ("dur_spt_min", self.sleep_minutes),
+            ("dur_day_total_IN_min", self.total_durations[0]),
+            ("dur_day_total_LIG_min", self.total_durations[1]),
+            ("dur_day_total_MOD_min", self.total_durations[2]),
+            ("dur_day_total_VIG_min", self.total_durations[3]),
+        ]
+    }
+
+    /// One line per required column naming the header it was read from.
+    fn describe(&self, headers: &StringRecord) -> Vec<String> {
+        self.mapping()
+            .into_iter()
+            .map(|(name, index)| {
+                let header = headers.get(index).unwrap_or("");
+                let note = if header.eq_ignore_ascii_case(name) {
+                    ""
+                } else {
+                    ", alias"
+                };
+                format!("{:<22} -> column {} ({}{})", name, index, header, note)
+            })
+            .collect()
+    }
+}
+
+/// Finds each required column by its own name or one of its aliases, ignoring case.
+fn locate_required_columns(
+    headers: &StringRecord,
+    aliases: &ColumnAliases,
+) -> Result<ColumnLookup, Vec<String>> {
     const DURATION_VARIANTS: [&str; 4] = ["IN", "LIG", "MOD", "VIG"];
 
     let mut missing = Vec::new();
+    let mut find = |name: &str| {
+        let names = aliases.get(name).map(Vec::as_slice).unwrap_or_default();
+        find_index(headers, name, names, &mut missing)
+    };
 
-    let id = find_index(headers, "ID", &mut missing);
-    let calendar_date = find_index(headers, "calendar_date", &mut missing);
-    let weekday = find_index(headers, "weekday", &mut missing);
-    let sleep_minutes = find_index(headers, "dur_spt_min", &mut missing);
+    let id = find("ID");
+    let calendar_date = find("calendar_date");
+    let weekday = find("weekday");
+    let sleep_minutes = find("dur_spt_min");
 
     let mut total_durations = [0usize; 4];
     for (slot, variant) in total_durations.iter_mut().zip(DURATION_VARIANTS.iter()) {
         let column_name = format!("dur_day_total_{}_min", variant);
-        *slot = find_index(headers, &column_name, &mut missing);
+        *slot = find(&column_name);
     }
 
     if missing.is_empty() {
@@ -616,8 +4688,21 @@ fn locate_required_columns(headers: &StringRecord) -> Result<ColumnLookup, Vec<S
     }
 }
 
-fn find_index(headers: &StringRecord, name: &str, missing: &mut Vec<String>) -> usize {
-    match headers.iter().position(|header| header == name) {
+fn find_index(
+    headers: &StringRecord,
+    name: &str,
+    aliases: &[String],
+    missing: &mut Vec<String>,
+) -> usize {
+    let position = |wanted: &str| {
+        headers
+            .iter()
+            .position(|header| header.eq_ignore_ascii_case(wanted))
+    };
+    match std::iter::once(name)
+        .chain(aliases.iter().map(String::as_str))
+        .find_map(position)
+    {
         Some(index) => index,
         None => {
             missing.push(name.to_string());
@@ -630,22 +4715,13 @@ fn extract_metrics_from_record(
     file: &Path,
     record: &StringRecord,
     columns: &ColumnLookup,
-) -> Option<DayMetrics> {
+) -> Result<DayMetrics, String> {
     const DURATION_VARIANTS: [&str; 4] = ["IN", "LIG", "MOD", "VIG"];
 
-    let id = match required_string_field(record, columns.id, "ID", file) {
-        Some(value) => value,
-        None => return None,
-    };
+    let id = required_string_field(record, columns.id, "ID", file)?;
     let calendar_date =
-        match required_string_field(record, columns.calendar_date, "calendar_date", file) {
-            Some(value) => value,
-            None => return None,
-        };
-    let weekday = match required_string_field(record, columns.weekday, "weekday", file) {
-        Some(value) => value,
-        None => return None,
-    };
+        required_string_field(record, columns.calendar_date, "calendar_date", file)?;
+    let weekday = required_string_field(record, columns.weekday, "weekday", file)?;
 
     let mut totals = [0f64; 4];
     for ((slot, &index), variant) in totals
@@ -653,23 +4729,16 @@ fn extract_metrics_from_record(
         .zip(columns.total_durations.iter())
         .zip(DURATION_VARIANTS.iter())
     {
-        *slot = match parse_f64_field(
+        *slot = parse_f64_field(
             record.get(index),
             &format!("dur_day_total_{}_min", variant),
             file,
-        ) {
-            Some(value) => value,
-            None => return None,
-        };
+        )?;
     }
 
-    let sleep_minutes =
-        match parse_f64_field(record.get(columns.sleep_minutes), "dur_spt_min", file) {
-            Some(value) => value,
-            None => return None,
-        };
+    let sleep_minutes = parse_f64_field(record.get(columns.sleep_minutes), "dur_spt_min", file)?;
 
-    Some(DayMetrics {
+    Ok(DayMetrics {
         id,
         calendar_date,
         weekday,
@@ -681,48 +4750,210 @@ fn extract_metrics_from_record(
     })
 }
 
+/// Returns the trimmed value, or the reason the row has to be skipped.
 fn required_string_field(
     record: &StringRecord,
     index: usize,
     column_name: &str,
     file: &Path,
-) -> Option<String> {
+) -> Result<String, String> {
     match record.get(index) {
-        Some(value) if !value.trim().is_empty() => Some(value.trim().to_string()),
-        _ => {
-            eprintln!(
-                "Skipping row in {} due to missing value for {}.",
-                file.display(),
-                column_name
-            );
-            None
-        }
+        Some(value) if !value.trim().is_empty() => Ok(value.trim().to_string()),
+        _ => Err(format!(
+            "Skipping row in {} due to missing value for {}.",
+            file.display(),
+            column_name
+        )),
     }
 }
 
-fn parse_f64_field(value: Option<&str>, column_name: &str, file: &Path) -> Option<f64> {
+fn parse_f64_field(value: Option<&str>, column_name: &str, file: &Path) -> Result<f64, String> {
     let raw = match value {
         Some(raw) if !raw.trim().is_empty() => raw.trim(),
         _ => {
-            eprintln!(
+            return Err(format!(
                 "Skipping row in {} due to missing value for {}.",
                 file.display(),
                 column_name
-            );
-            return None;
+            ));
         }
     };
 
-    match raw.parse::<f64>() {
-        Ok(number) => Some(number),
-        Err(err) => {
-            eprintln!(
-                "Skipping row in {} due to parse error in {}: {}",
-                file.display(),
-                column_name,
-                err
-            );
-            None
+    raw.parse::<f64>().map_err(|err| {
+        format!(
+            "Skipping row in {} due to parse error in {}: {}",
+            file.display(),
+            column_name,
+            err
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_subject_range() {
+        let rules = SubjectRules {
+            digits: 4,
+            studies: default_studies(),
+        };
+        assert_eq!(
+            expand_subject_range(&rules, "7008-7011").unwrap(),
+            ["7008", "7009", "7010", "7011"]
+        );
+        assert!(expand_subject_range(&rules, "7011-7008").is_err());
+    }
+
+    #[test]
+    fn rejects_overflowing_and_oversized_ranges() {
+        let wide = SubjectRules {
+            digits: 21,
+            studies: vec![StudyRule {
+                prefixes: vec!["7".to_string()],
+                study: "ObservationalStudy".to_string(),
+                dataset: "act-obs-final-test-*".to_string(),
+            }],
+        };
+        assert!(
+            expand_subject_range(&wide, "700000000000000000000-700000000000000000001").is_err()
+        );
+
+        let long = SubjectRules {
+            digits: 10,
+            studies: wide.studies.clone(),
+        };
+        assert!(expand_subject_range(&long, "7000000001-7999999999").is_err());
+    }
+
+    #[test]
+    fn parses_dataset_override() {
+        let studies = default_studies();
+        let scoped = parse_dataset_override("interventionstudy=act-int-final-test-2", &studies)
+            .unwrap()
+            .unwrap();
+        assert_eq!(scoped.study.as_deref(), Some("InterventionStudy"));
+        assert_eq!(scoped.dataset, "act-int-final-test-2");
+
+        let bare = parse_dataset_override(" act-obs-final-test-* ", &studies)
+            .unwrap()
+            .unwrap();
+        assert_eq!(bare.study, None);
+        assert_eq!(bare.dataset, "act-obs-final-test-*");
+
+        assert!(parse_dataset_override("", &studies).unwrap().is_none());
+        assert!(parse_dataset_override("OtherStudy=act-obs-final-test-2", &studies).is_err());
+    }
+
+    #[test]
+    fn scoped_dataset_drops_matches_from_other_studies() {
+        let found = |study: &str, dataset: &str| SubjectMatch {
+            share_root: PathBuf::from("/share"),
+            study: study.to_string(),
+            dataset: dataset.to_string(),
+        };
+        let mut matches = vec![
+            found("ObservationalStudy", "act-obs-final-test-2"),
+            found("InterventionStudy", "act-int-final-test-2"),
+        ];
+        let scoped = parse_dataset_override(
+            "ObservationalStudy=act-obs-final-test-2",
+            &default_studies(),
+        )
+        .unwrap()
+        .unwrap();
+        matches.retain(|found| scoped.selects(found));
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].study, "ObservationalStudy");
+
+        let pattern = parse_dataset_override("ObservationalStudy=act-obs-*", &default_studies())
+            .unwrap()
+            .unwrap();
+        assert!(pattern.selects(&found("ObservationalStudy", "act-obs-final-test-3")));
+        assert!(!pattern.selects(&found("InterventionStudy", "act-obs-final-test-3")));
+    }
+
+    #[test]
+    fn parses_part5_file_name() {
+        let params = Part5Params::parse("part5_daysummary_MM_L44.8M100.6V428.8_T5A5.csv").unwrap();
+        assert_eq!(params.window, WindowType::MidnightToMidnight);
+        assert_eq!(params.light_threshold, 44.8);
+        assert_eq!(params.moderate_threshold, 100.6);
+        assert_eq!(params.vigorous_threshold, 428.8);
+        assert_eq!(params.time_threshold, 5.0);
+        assert_eq!(params.angle_threshold, 5.0);
+    }
+
+    #[test]
+    fn parses_part5_file_name_ignoring_case() {
+        let params = Part5Params::parse("PART5_DAYSUMMARY_ww_L40M100V400_T10A3.CSV").unwrap();
+        assert_eq!(params.window, WindowType::WakingToWaking);
+        assert_eq!(params.light_threshold, 40.0);
+        assert_eq!(params.time_threshold, 10.0);
+        assert_eq!(params.angle_threshold, 3.0);
+    }
+
+    #[test]
+    fn rejects_other_part5_file_names() {
+        for name in [
+            "part5_daysummary_MM_L44.8M100.6V428.8_T5A5_site2.csv",
+            "part5_daysummary_XX_L44.8M100.6V428.8_T5A5.csv",
+            "part5_daysummary_MM_L44.8M100.6_T5A5.csv",
+            "part5_daysummary_MM_L44.8M100.6V428.8.csv",
+            "part5_daysummary_MM_L44.8M100.6V428.8_T5A5.txt",
+            "part5_personsummary_MM_L44.8M100.6V428.8_T5A5.csv",
+            "boost_part5_export.csv",
+        ] {
+            assert!(Part5Params::parse(name).is_none(), "{}", name);
         }
     }
+
+    #[test]
+    fn orders_versions_numerically() {
+        assert_eq!(compare_versions("3.10.0", "3.2.6"), Ordering::Greater);
+        assert_eq!(compare_versions("3.2.6", "3.2.6"), Ordering::Equal);
+        assert_eq!(compare_versions("3.2", "3.2.1"), Ordering::Less);
+        assert_eq!(
+            compare_versions("act-obs-final-test-10", "act-obs-final-test-2"),
+            Ordering::Greater
+        );
+
+        let mut datasets = vec!["final-test-2", "final-test-10", "final-test-3"];
+        datasets.sort_by(|a, b| compare_versions(b, a));
+        assert_eq!(datasets, ["final-test-10", "final-test-3", "final-test-2"]);
+    }
+
+    #[test]
+    fn matches_wildcards_ignoring_case() {
+        let pattern = "part5_daysummary_MM_*.csv";
+        assert!(wildcard_match(
+            pattern,
+            "PART5_DAYSUMMARY_mm_L44.8M100.6V428.8_T5A5.CSV"
+        ));
+        assert!(wildcard_match(pattern, "part5_daysummary_MM_.csv"));
+        assert!(!wildcard_match(
+            pattern,
+            "part5_daysummary_WW_L44.8M100.6V428.8_T5A5.csv"
+        ));
+
+        assert!(wildcard_match(
+            "act-obs-final-test-?",
+            "act-obs-final-test-2"
+        ));
+        assert!(!wildcard_match(
+            "act-obs-final-test-?",
+            "act-obs-final-test-10"
+        ));
+        assert!(!wildcard_match(
+            "act-obs-final-test-?",
+            "act-obs-final-test-"
+        ));
+        assert!(wildcard_match("*-test-*", "act-int-final-test-10"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match(
+            "act-obs-final-test-2",
+            "act-obs-final-test-20"
+        ));
+    }
 }
@@ -0,0 +1,349 @@
+use crate::{
+    build_variant_sessions, list_subjects, locate_match, sort_metrics_by_date,
+    weekly_summary_lines, AppError, AppResult, DayMetrics, Session, Settings, SubjectMatch,
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Row, Table, Wrap},
+    DefaultTerminal, Frame,
+};
+use std::{collections::HashMap, path::PathBuf};
+
+/// Browses the subjects under the configured share until the user quits.
+pub fn run(mut settings: Settings) -> AppResult<()> {
+    // Every matching part5 variant is loaded; `v` switches between them.
+    settings.all_variants = true;
+    // Verbose notes on stderr would be drawn over the screen.
+    settings.verbose = false;
+
+    let subjects = list_subjects(&settings);
+    if subjects.is_empty() {
+        return Err(AppError::Other(format!(
+            "No subject folders found under {}",
+            settings.share_path.display()
+        )));
+    }
+
+    let mut app = App::new(settings, subjects);
+    let mut terminal = ratatui::init();
+    let result = app.event_loop(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+struct App {
+    settings: Settings,
+    // Each subject with the folder it was listed from, which is also where it is loaded from.
+    subjects: Vec<(String, SubjectMatch, PathBuf)>,
+    list_state: ListState,
+    // Loaded subjects are kept so moving back to one does not re-read the share.
+    sessions: HashMap<String, Result<Vec<Session>, String>>,
+    variant_index: usize,
+    day_scroll: usize,
+}
+
+impl App {
+    fn new(settings: Settings, subjects: Vec<(String, SubjectMatch, PathBuf)>) -> Self {
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        App {
+            settings,
+            subjects,
+            list_state,
+            sessions: HashMap::new(),
+            variant_index: 0,
+            day_scroll: 0,
+        }
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> AppResult<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                KeyCode::Enter => self.load_selected(),
+                KeyCode::Char('r') => {
+                    if let Some(subject) = self.selected_subject() {
+                        self.sessions.remove(&subject);
+                    }
+                    self.load_selected();
+                }
+                KeyCode::Char('v') => self.next_variant(),
+                KeyCode::PageDown => self.day_scroll = self.day_scroll.saturating_add(5),
+                KeyCode::PageUp => self.day_scroll = self.day_scroll.saturating_sub(5),
+                _ => {}
+            }
+        }
+    }
+
+    fn selected_subject(&self) -> Option<String> {
+        self.list_state
+            .selected()
+            .and_then(|index| self.subjects.get(index))
+            .map(|(number, _, _)| number.clone())
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let last = self.subjects.len().saturating_sub(1) as isize;
+        let next = (current + delta).clamp(0, last) as usize;
+        self.list_state.select(Some(next));
+        self.variant_index = 0;
+        self.day_scroll = 0;
+    }
+
+    fn next_variant(&mut self) {
+        let count = match self
+            .selected_subject()
+            .and_then(|subject| self.sessions.get(&subject))
+        {
+            Some(Ok(sessions)) => sessions.len(),
+            _ => return,
+        };
+        self.variant_index = (self.variant_index + 1) % count.max(1);
+        self.day_scroll = 0;
+    }
+
+    fn load_selected(&mut self) {
+        let Some((subject, found, _)) = self
+            .list_state
+            .selected()
+            .and_then(|index| self.subjects.get(index))
+        else {
+            return;
+        };
+        if self.sessions.contains_key(subject) {
+            return;
+        }
+
+        // Read the folder that was listed; the prefix rules may point elsewhere or nowhere.
+        let location = locate_match(&self.settings, subject, found);
+        let sessions = match build_variant_sessions(&self.settings, subject, location) {
+            Ok(results) => {
+                let (loaded, failed): (Vec<_>, Vec<_>) =
+                    results.into_iter().partition(Result::is_ok);
+                let skipped: Vec<String> = failed
+                    .into_iter()
+                    .filter_map(Result::err)
+                    .map(|err| format!("Warning: skipping a part5 variant: {}", err))
+                    .collect();
+                let mut loaded: Vec<Session> = loaded.into_iter().filter_map(Result::ok).collect();
+                if loaded.is_empty() {
+                    Err(skipped.join("\n"))
+                } else {
+                    for session in &mut loaded {
+                        session.warnings.extend(skipped.iter().cloned());
+                    }
+                    Ok(loaded)
+                }
+            }
+            Err(err) => Err(err.to_string()),
+        };
+        self.sessions.insert(subject.clone(), sessions);
+        self.variant_index = 0;
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let outer = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(frame.area());
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(18), Constraint::Min(20)])
+            .split(outer[0]);
+
+        self.draw_subject_list(frame, columns[0]);
+
+        let selected = self.selected_subject();
+        match selected
+            .as_ref()
+            .and_then(|subject| self.sessions.get(subject))
+        {
+            Some(Ok(sessions)) => {
+                let index = self.variant_index.min(sessions.len().saturating_sub(1));
+                if let Some(session) = sessions.get(index) {
+                    let variant = format!("variant {} of {}", index + 1, sessions.len());
+                    draw_session(frame, columns[1], session, &variant, &mut self.day_scroll);
+                }
+            }
+            Some(Err(message)) => frame.render_widget(
+                Paragraph::new(message.as_str())
+                    .wrap(Wrap { trim: false })
+                    .block(Block::default().borders(Borders::ALL).title("Error")),
+                columns[1],
+            ),
+            None => {
+                let directory = self
+                    .list_state
+                    .selected()
+                    .and_then(|index| self.subjects.get(index))
+                    .map(|(_, _, directory)| directory.display().to_string())
+                    .unwrap_or_default();
+                frame.render_widget(
+                    Paragraph::new(vec![
+                        Line::from("Press Enter to load this subject."),
+                        Line::from(""),
+                        Line::from(directory),
+                    ])
+                    .wrap(Wrap { trim: false })
+                    .block(Block::default().borders(Borders::ALL).title("Subject")),
+                    columns[1],
+                );
+            }
+        }
+
+        frame.render_widget(
+            Paragraph::new(
+                "↑/↓ select  Enter load  r reload  v next variant  PgUp/PgDn scroll days  q quit",
+            ),
+            outer[1],
+        );
+    }
+
+    fn draw_subject_list(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .subjects
+            .iter()
+            .map(|(number, _, _)| {
+                let marker = match self.sessions.get(number) {
+                    Some(Ok(_)) => "*",
+                    Some(Err(_)) => "!",
+                    None => " ",
+                };
+                ListItem::new(format!("{} sub-{}", marker, number))
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Subjects ({})", self.subjects.len())),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.list_state);
+    }
+}
+
+fn draw_session(
+    frame: &mut Frame,
+    area: Rect,
+    session: &Session,
+    variant: &str,
+    day_scroll: &mut usize,
+) {
+    let summary_lines = weekly_summary_lines(session.weekly_summary.as_ref());
+    let file_rows = (session.csv_files.len() + session.warnings.len() + 1).clamp(1, 6) as u16;
+
+    let panes = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(file_rows + 2), Constraint::Min(5)])
+        .split(area);
+    let lower = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Min(40), Constraint::Length(54)])
+        .split(panes[1]);
+
+    let mut file_lines: Vec<Line> = Vec::new();
+    if let Some(params) = &session.part5 {
+        file_lines.push(Line::from(format!(
+            "Part5 {}: {}",
+            variant,
+            params.describe()
+        )));
+    }
+    file_lines.extend(
+        session
+            .csv_files
+            .iter()
+            .map(|path| Line::from(path.display().to_string())),
+    );
+    if session.csv_files.is_empty() {
+        file_lines.push(Line::from(
+            "No matching files found; verify the subject data is available.",
+        ));
+    }
+    file_lines.extend(
+        session
+            .warnings
+            .iter()
+            .map(|warning| Line::from(warning.as_str())),
+    );
+    frame.render_widget(
+        Paragraph::new(file_lines).block(Block::default().borders(Borders::ALL).title(format!(
+            "Files for sub-{} ({})",
+            session.subject_number, session.location.dataset
+        ))),
+        panes[0],
+    );
+
+    let mut days: Vec<DayMetrics> = session.activity_data.values().flatten().cloned().collect();
+    sort_metrics_by_date(&mut days);
+    let total_days = days.len();
+    // Borders and the header row take three lines; stop once the last day is on screen.
+    let visible_rows = usize::from(lower[0].height.saturating_sub(3));
+    *day_scroll = (*day_scroll).min(total_days.saturating_sub(visible_rows));
+    let rows: Vec<Row> = days
+        .into_iter()
+        .skip(*day_scroll)
+        .map(|day| {
+            Row::new(vec![
+                day.id,
+                day.calendar_date,
+                day.weekday,
+                format!("{:.1}", day.sleep_minutes),
+                format!("{:.1}", day.total_in_min),
+                format!("{:.1}", day.total_lig_min),
+                format!("{:.1}", day.total_mod_min),
+                format!("{:.1}", day.total_vig_min),
+            ])
+        })
+        .collect();
+    let widths = [
+        Constraint::Length(8),
+        Constraint::Length(12),
+        Constraint::Length(10),
+        Constraint::Length(9),
+        Constraint::Length(9),
+        Constraint::Length(9),
+        Constraint::Length(9),
+        Constraint::Length(9),
+    ];
+    let table = Table::new(rows, widths)
+        .header(
+            Row::new(vec![
+                "ID", "Date", "Weekday", "Sleep", "IN", "LIG", "MOD", "VIG",
+            ])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Days ({}, minutes)", total_days)),
+        );
+    frame.render_widget(table, lower[0]);
+
+    let summary: Vec<Line> = summary_lines.into_iter().map(Line::from).collect();
+    frame.render_widget(
+        Paragraph::new(summary).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Weekly summary"),
+        ),
+        lower[1],
+    );
+}